@@ -0,0 +1,176 @@
+// Rotated backups of a tree file, made when it's opened and closed. They're
+// kept in a directory next to the tree, named by the UTC time they were made:
+//
+//    project.backups/project-20181030T120000Z.dv
+//
+// Two settings of the root Idea control them: backup_count, the number of
+// backups to keep (5 by default, 0 turns backups off), and backup_dir, the
+// directory to keep them in, relative to the tree file. A backup which would
+// be the same as the newest one isn't made, and rotation always keeps the
+// newest backup from before the session opened the tree.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use yaml_rust::Yaml;
+
+use idea::{IdeaTree, utc_now, utc_time};
+use formats::text;
+use error::{Result, Error};
+
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    // When the backup was made, like 2018-10-30 12:00:00 UTC
+    pub timestamp: String,
+}
+
+fn tree_stem(tree_path: &Path) -> String {
+    tree_path.file_stem().map_or("tree".to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+// The directory backups of a tree are kept in, unless it's in memory
+pub fn backup_dir(tree: &IdeaTree) -> Result<Option<PathBuf>> {
+    let tree_path = match tree.path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let tree_dir = tree_path.parent().unwrap_or(Path::new(""));
+    Ok(Some(match tree.get_setting(1, "backup_dir")? {
+        Yaml::String(dir) => tree_dir.join(dir),
+        _ => tree_dir.join(format!("{}.backups", tree_stem(tree_path))),
+    }))
+}
+
+pub fn backup_count(tree: &IdeaTree) -> Result<usize> {
+    match tree.get_setting(1, "backup_count")? {
+        Yaml::Integer(count) if count >= 0 => Ok(count as usize),
+        Yaml::BadValue => Ok(DEFAULT_BACKUP_COUNT),
+        _ => Err(Error::DaVinci("The backup_count setting must be a number of backups to keep".to_string())),
+    }
+}
+
+// Read the time a backup was made from its file name: 20181030T120000Z,
+// maybe followed by a counter for backups made in the same second. Returns
+// the time, the counter, and the time in a readable form.
+fn parse_backup_name(stem: &str, file_name: &str) -> Option<(String, u32, String)> {
+    let prefix = format!("{}-", stem);
+    if !file_name.starts_with(&prefix) || !file_name.ends_with(".dv") {
+        return None;
+    }
+
+    let name = &file_name[prefix.len()..file_name.len() - 3];
+    if name.len() < 16 || !name.is_char_boundary(16) {
+        return None;
+    }
+    let (time, counter) = name.split_at(16);
+    let digits: String = time.chars().filter(|c| c.is_ascii_digit()).collect();
+    if &time[8..9] != "T" || &time[15..16] != "Z" || digits.len() != 14 {
+        return None;
+    }
+    let counter = match counter {
+        "" => 1,
+        counter if counter.starts_with("-") => counter[1..].parse().ok()?,
+        _ => return None,
+    };
+
+    let timestamp = format!("{}-{}-{} {}:{}:{} UTC", &digits[0..4], &digits[4..6], &digits[6..8], &digits[8..10], &digits[10..12], &digits[12..14]);
+    Some((time.to_string(), counter, timestamp))
+}
+
+fn backup_time(year: i64, month: i64, day: i64, seconds_of_day: i64) -> String {
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day,
+        seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60)
+}
+
+// The tree's backups, newest first
+pub fn list_backups(tree: &IdeaTree) -> Result<Vec<Backup>> {
+    Ok(dated_backups(tree)?.into_iter().map(|(_, backup)| backup).collect())
+}
+
+// The tree's backups, newest first, with the times in their names
+fn dated_backups(tree: &IdeaTree) -> Result<Vec<(String, Backup)>> {
+    let (dir, stem) = match (backup_dir(tree)?, tree.path()) {
+        (Some(dir), Some(tree_path)) => (dir, tree_stem(tree_path)),
+        _ => return Ok(Vec::new()),
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let parsed = path.file_name().and_then(|name| parse_backup_name(&stem, &name.to_string_lossy()));
+        if let Some((time, counter, timestamp)) = parsed {
+            backups.push((time, counter, Backup { path, timestamp }));
+        }
+    }
+
+    backups.sort_by(|a, b| (&b.0, b.1).cmp(&(&a.0, a.1)));
+    Ok(backups.into_iter().map(|(time, _, backup)| (time, backup)).collect())
+}
+
+// Whether a backup holds the same Ideas as the tree
+fn is_same_as(tree: &IdeaTree, backup: &Backup) -> Result<bool> {
+    let backed_up = IdeaTree::open_read_only(&backup.path)?;
+    Ok(text::to_text(&backed_up.all_ideas()?) == text::to_text(&tree.all_ideas()?))
+}
+
+// Back up the tree and delete the oldest backups beyond backup_count. Returns
+// the new backup, or the newest one if the tree hasn't changed since it was
+// made. Returns nothing if backups are turned off or the tree is in memory.
+pub fn back_up(tree: &IdeaTree) -> Result<Option<Backup>> {
+    let count = backup_count(tree)?;
+    let (dir, stem) = match (backup_dir(tree)?, tree.path()) {
+        (Some(dir), Some(tree_path)) if count > 0 => (dir, tree_stem(tree_path)),
+        _ => return Ok(None),
+    };
+    if let Some(newest) = list_backups(tree)?.into_iter().next() {
+        if is_same_as(tree, &newest)? {
+            return Ok(Some(newest));
+        }
+    }
+    fs::create_dir_all(&dir)?;
+
+    let (year, month, day, seconds_of_day) = utc_now();
+    let time = backup_time(year, month, day, seconds_of_day);
+    let mut path = dir.join(format!("{}-{}.dv", stem, time));
+    let mut counter = 1;
+    while path.exists() {
+        counter += 1;
+        path = dir.join(format!("{}-{}-{}.dv", stem, time, counter));
+    }
+    tree.back_up_to(&path)?;
+
+    // However many backups the session made, the newest one from before it
+    // is kept, so its changes can always be undone
+    let session_start = {
+        let (year, month, day, seconds_of_day) = utc_time(tree.opened_at());
+        backup_time(year, month, day, seconds_of_day)
+    };
+    let backups = dated_backups(tree)?;
+    let newest_before_session = backups.iter().position(|&(ref time, _)| *time < session_start);
+    for (idx, (_, old_backup)) in backups.into_iter().enumerate().skip(count) {
+        if Some(idx) != newest_before_session {
+            fs::remove_file(old_backup.path)?;
+        }
+    }
+
+    let timestamp = format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day,
+        seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60);
+    Ok(Some(Backup { path, timestamp }))
+}
+
+// Copy a backup into a new tree file, leaving the tree and its backups alone
+pub fn restore_backup<P: AsRef<Path>>(backup: &Backup, destination: P) -> Result<()> {
+    let destination = destination.as_ref();
+    if destination.exists() {
+        return Err(Error::DaVinci(format!("{} already exists", destination.display())));
+    }
+
+    IdeaTree::open_read_only(&backup.path)?.back_up_to(destination)
+}
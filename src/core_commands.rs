@@ -1,9 +1,19 @@
 use repl::*;
 use error::*;
 use idea::IdeaTree;
+use formats::directory::DirImport;
+use formats::todotxt;
+use git::GitRepo;
+use lock::live_session;
+use backup;
+use diff::{diff_trees, format_changes, changes_to_json};
 use std::collections::HashMap;
+use std::path::Path;
 
-use edit_rs::get_input;
+use yaml_rust::{Yaml, YamlLoader};
+
+// Meta Ideas whose descriptions are read as YAML data
+const YAML_META_IDEAS: [&str; 4] = [".settings", ".shortcuts", ".macros", ".import-dir"];
 
 pub fn core_commands() -> HashMap<String, Command> {
     let mut commands = HashMap::new();
@@ -21,7 +31,7 @@ pub fn core_commands() -> HashMap<String, Command> {
             description: "Display the version of Da Vinci Bot which is installed.",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Zero, |repl, tree, _args| { println!("{}", VERSION); Ok(()) }),
+                CommandHandler::new(CommandArgs::Zero, |repl, _tree, _args| { repl.output().println(VERSION); Ok(()) }),
             ],
         });
         commands.insert("print".to_string(), Command {
@@ -30,16 +40,16 @@ pub fn core_commands() -> HashMap<String, Command> {
             handlers: vec![CommandHandler::new(CommandArgs::Zero, |repl, tree, _args| repl.print(tree, false))],
         });
         commands.insert("listall".to_string(), Command {
-            description: "List all children of the current Idea, including hidden ones.",
+            description: "List all children of the current Idea, including hidden ones. Meta Ideas aren't passed through pipes.",
             delimiter: None,
-            handlers: vec![CommandHandler::new(CommandArgs::Zero, |repl, tree, _args| list(repl, tree, true))],
+            handlers: vec![CommandHandler::returning(CommandArgs::Zero, |repl, tree, _args| list(repl, tree, true))],
         });
         // TODO list needs to allow pagination
         commands.insert("list".to_string(), Command {
             description: "List children of the current Idea",
             delimiter: Some(" ".to_string()),
             handlers: vec![
-                CommandHandler::new(CommandArgs::Zero, |repl, tree, _args| list(repl, tree, false)),
+                CommandHandler::returning(CommandArgs::Zero, |repl, tree, _args| list(repl, tree, false)),
                 CommandHandler::new(CommandArgs::VarArgs, |repl, tree, args| list_with_tags(repl, tree, args)),
             ],
         });
@@ -47,49 +57,49 @@ pub fn core_commands() -> HashMap<String, Command> {
             description: "Select an Idea",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Amount(1), select),
+                CommandHandler::returning(CommandArgs::Amount(1), select),
             ],
         });
         commands.insert("up".to_string(), Command {
             description: "Select the current Idea's parent Idea",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Zero, |repl, tree, _args| select(repl, tree, vec!["^".to_string()])),
+                CommandHandler::returning(CommandArgs::Zero, |repl, tree, _args| select(repl, tree, vec!["^".to_string()])),
             ],
         });
         commands.insert("root".to_string(), Command {
             description: "Select the root Idea of the current Tree",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Zero, |repl, tree, _args| select(repl, tree, vec!["@".to_string()])),
+                CommandHandler::returning(CommandArgs::Zero, |repl, tree, _args| select(repl, tree, vec!["@".to_string()])),
             ],
         });
         commands.insert("add".to_string(), Command {
             description: "Add a new Idea as a child of the current one.",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Zero, add_multiple),
-                CommandHandler::new(CommandArgs::Amount(1), add),
+                CommandHandler::returning(CommandArgs::Zero, add_multiple).mutating(),
+                CommandHandler::returning(CommandArgs::Amount(1), add).mutating(),
             ],
         });
         commands.insert("tag".to_string(), Command {
             description: "Add tag(s) to the current Idea",
             delimiter: Some(" ".to_string()),
             handlers: vec![
-                CommandHandler::new(CommandArgs::Zero, tag_multiple),
-                CommandHandler::new(CommandArgs::Minimum(1), tag),
+                CommandHandler::returning(CommandArgs::Zero, tag_multiple).mutating(),
+                CommandHandler::returning(CommandArgs::Minimum(1), tag).mutating(),
             ],
         });
         commands.insert("untag".to_string(), Command {
             description: "Remove tag(s) from the current Idea",
             delimiter: Some(" ".to_string()),
-            handlers: vec![CommandHandler::new(CommandArgs::Minimum(1), untag)],
+            handlers: vec![CommandHandler::returning(CommandArgs::Minimum(1), untag).mutating()],
             // TODO untag multiple?
         });
         commands.insert("cleartags".to_string(), Command {
             description: "Clear all tags from the current Idea",
             delimiter: None,
-            handlers: vec![CommandHandler::new(CommandArgs::Zero, cleartags)],
+            handlers: vec![CommandHandler::returning(CommandArgs::Zero, cleartags).mutating()],
             // TODO cleartags implementation that allows selecting multiple
             // Ideas? (although, cleartags already takes zero arguments so it
             // would need a different command name
@@ -98,44 +108,126 @@ pub fn core_commands() -> HashMap<String, Command> {
             description: "Move Idea(s) from one parent to another",
             delimiter: Some("->".to_string()),
             handlers: vec![
-                CommandHandler::new(CommandArgs::Amount(2), move_both_args),
-                CommandHandler::new(CommandArgs::Amount(1), move_one_arg),
-                CommandHandler::new(CommandArgs::Zero, move_multiple),
+                CommandHandler::returning(CommandArgs::Amount(2), move_both_args).mutating(),
+                CommandHandler::returning(CommandArgs::Amount(1), move_one_arg).mutating(),
+                CommandHandler::returning(CommandArgs::Zero, move_multiple).mutating(),
+            ],
+        });
+        commands.insert("copy".to_string(), Command {
+            description: "Copy an Idea and its descendants under a parent, which can be in another open tree: copy [expression] -> [parent] -> [new name]",
+            delimiter: Some("->".to_string()),
+            handlers: vec![
+                // Not mutating, because copying out of a read-only tree into
+                // another one is fine. A read-only destination refuses it.
+                CommandHandler::returning(CommandArgs::Range { min: 2, max: 3 }, copy),
+            ],
+        });
+        commands.insert("new".to_string(), Command {
+            description: "List the templates in the nearest .templates Idea, or add a child of the current Idea from one: new [template] [name]",
+            delimiter: None,
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Zero, list_templates),
+                CommandHandler::returning(CommandArgs::Amount(1), new_from_template).mutating(),
+            ],
+        });
+        commands.insert("open".to_string(), Command {
+            description: "Open another tree file in this session, under its file name or a given name: open [file.dv] -> [name]",
+            delimiter: Some("->".to_string()),
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Range { min: 1, max: 2 }, open),
+            ],
+        });
+        commands.insert("close".to_string(), Command {
+            description: "Close a tree opened in this session",
+            delimiter: None,
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Amount(1), close),
+            ],
+        });
+        commands.insert("switch".to_string(), Command {
+            description: "List the trees open in this session, or switch to one of them",
+            delimiter: None,
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Zero, list_trees),
+                CommandHandler::new(CommandArgs::Amount(1), switch),
+            ],
+        });
+        commands.insert("backup".to_string(), Command {
+            description: "Back up the tree now, and list its backups",
+            delimiter: None,
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Zero, backup),
+            ],
+        });
+        commands.insert("restore-backup".to_string(), Command {
+            description: "List the tree's backups, or restore one of them into a new file: restore-backup [n] -> [file.dv]",
+            delimiter: Some("->".to_string()),
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Zero, list_backups),
+                CommandHandler::new(CommandArgs::Range { min: 1, max: 2 }, restore_backup),
             ],
         });
         commands.insert("describe".to_string(), Command {
             description: "Edit the current Idea's description",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Amount(1), describe),
-                CommandHandler::new(CommandArgs::Zero, describe),
+                CommandHandler::new(CommandArgs::Amount(1), describe).mutating(),
+                CommandHandler::new(CommandArgs::Zero, describe).mutating(),
             ],
         });
         commands.insert("rename".to_string(), Command {
             description: "Rename an Idea",
             delimiter: Some("->".to_string()),
             handlers: vec![
-                CommandHandler::new(CommandArgs::Amount(2), rename_any),
-                CommandHandler::new(CommandArgs::Amount(1), rename_selected),
-                CommandHandler::new(CommandArgs::Amount(0), rename_selected),
+                CommandHandler::new(CommandArgs::Amount(2), rename_any).mutating(),
+                CommandHandler::new(CommandArgs::Amount(1), rename_selected).mutating(),
+                CommandHandler::new(CommandArgs::Amount(0), rename_selected).mutating(),
             ],
         });
         commands.insert("search".to_string(), Command {
             description: "Search for Ideas containing a given phrase anywhere",
             delimiter: None,
             handlers: vec![
-                CommandHandler::new(CommandArgs::Amount(1), search),
+                CommandHandler::returning(CommandArgs::Amount(1), search),
             ],
         });
 
-        // TODO export command with org mode
         commands.insert("export".to_string(), Command {
-            description: "Export the current Idea into another file format (such as Emacs org file)",
+            description: "Export the current Idea into another file format (.org, .md, .json, .opml, .html, .dot, .csv, .tsv, .ics, todo.txt or *.todo.txt)",
             delimiter: None,
             handlers: vec![
                 CommandHandler::new(CommandArgs::Amount(1), export),
             ],
         });
+        commands.insert("import".to_string(), Command {
+            description: "Import Ideas from a file (.org, .md, .json, .opml, .csv, .tsv) as children of the current Idea, or another one: import [file] -> [parent]. JSON can be imported with --keep-ids to merge Ideas by id, and completed tasks in a todo.txt or *.todo.txt file are tagged done",
+            delimiter: Some("->".to_string()),
+            handlers: vec![
+                CommandHandler::returning(CommandArgs::Range { min: 1, max: 2 }, import).mutating(),
+            ],
+        });
+        commands.insert("import-dir".to_string(), Command {
+            description: "Import a directory from disk as a child of the current Idea, or sync one imported before: import-dir [path] --include [glob] --exclude [glob] --depth [n]. With no arguments, syncs the current Idea with the directory it was imported from",
+            delimiter: Some(" --".to_string()),
+            handlers: vec![
+                CommandHandler::returning(CommandArgs::Zero, sync_dir).mutating(),
+                CommandHandler::returning(CommandArgs::Minimum(1), import_dir).mutating(),
+            ],
+        });
+        commands.insert("commit".to_string(), Command {
+            description: "Commit the tree file to the git repository it's in, with a summary of changed Ideas as the message unless one is given",
+            delimiter: None,
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Maximum(1), commit),
+            ],
+        });
+        commands.insert("diff".to_string(), Command {
+            description: "Show how this tree changed since another version of it, in a file or a git revision like HEAD~1: diff [file.dv or revision] --under [expression] --json",
+            delimiter: Some(" --".to_string()),
+            handlers: vec![
+                CommandHandler::new(CommandArgs::Minimum(1), diff),
+            ],
+        });
 
         // TODO loop through lines of the description and prompt for select expressions to add them as children. Blank select expression = don't turn into an idea. Once this is done, prompt asking whether to archive this idea. Also, while looping, should probably remove lines as they get ideaifyied?
         // TODO reordering children
@@ -169,45 +261,139 @@ fn print_command_help(repl: &mut Repl, _tree: &mut IdeaTree, args: Vec<String>)
     Ok(())
 }
 
-fn select(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
-    let id_to_select = repl.select_from_expression(tree, &args[0])?;
+fn select(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    let (tree_name, id_to_select) = repl.select_in_any_tree(tree, &args[0])?;
+    // Selecting an Idea in another open tree switches to that tree
+    if let Some(tree_name) = tree_name {
+        repl.switch_tree(tree, &tree_name)?;
+    }
     repl.select(id_to_select, tree)?;
 
-    Ok(())
+    Ok(vec![id_to_select])
 }
 
-fn move_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
-    let select_expression = &repl.prompt_for_args(vec!["destination?"])?[0];
-    let parent_id = repl.select_from_expression(tree, select_expression)?;
-    repl.prompt(" idea to move:", |ref repl, select_expression| {
-        let id_to_move = repl.select_from_expression(tree, &select_expression)?;
-        tree.set_parent(id_to_move, parent_id)?;
+fn move_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<Vec<i64>> {
+    let destination = repl.prompt_for_args(vec!["destination?"])?.remove(0);
+    repl.select_in_any_tree(tree, &destination)?;
+    let mut moved_ids = Vec::new();
+    repl.prompt(" idea to move:", |repl, select_expression| {
+        moved_ids.extend(move_idea(repl, tree, select_expression, &destination)?);
         Ok(true)
     }, false); // Don't save idea movement args in history
 
-    Ok(())
+    Ok(moved_ids)
 }
 
-fn move_one_arg(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
-    let id_to_move = repl.select_from_expression(tree, args[0].as_str())?;
+fn move_one_arg(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    // Check the Idea to move before prompting for its destination
+    repl.select_in_any_tree(tree, args[0].as_str())?;
     let further_arg = repl.prompt_for_args(vec!["desination?"])?;
-    let parent_selector = further_arg[0].as_str();
-    let parent_id = repl.select_from_expression(tree, parent_selector)?;
-    tree.set_parent(id_to_move, parent_id)
+    move_idea(repl, tree, args[0].as_str(), further_arg[0].as_str())
+}
+
+fn move_both_args(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    move_idea(repl, tree, args[0].as_str(), args[1].as_str())
+}
+
+// Move an Idea under a new parent, which can be in another open tree. The
+// result set holds the moved Idea if it ends up in the current tree.
+fn move_idea(repl: &mut Repl, tree: &mut IdeaTree, source: &str, destination: &str) -> Result<Vec<i64>> {
+    let (source_tree, id_to_move) = repl.select_in_any_tree(tree, source)?;
+    let (destination_tree, parent_id) = repl.select_in_any_tree(tree, destination)?;
+
+    let moved_id = if source_tree == destination_tree {
+        repl.tree_mut(tree, &source_tree)?.set_parent(id_to_move, parent_id)?;
+        id_to_move
+    } else {
+        // Ideas can't keep their ids in another tree, so the subtree is
+        // copied there, then deleted here. Anything that would stop the
+        // delete is checked before copying.
+        if id_to_move == 1 {
+            return Err(Error::DaVinci("Cannot move the Root idea.".to_string()));
+        }
+        repl.tree_mut(tree, &source_tree)?.check_can_change_subtree(id_to_move, "move")?;
+        let copy_id = copy_between_trees(repl, tree, (&source_tree, id_to_move), (&destination_tree, parent_id), None)?;
+        if let Err(e) = repl.tree_mut(tree, &source_tree)?.delete_subtree(id_to_move) {
+            // Don't leave the Idea in both trees
+            repl.tree_mut(tree, &destination_tree)?.delete_subtree(copy_id)?;
+            return Err(e);
+        }
+        copy_id
+    };
+
+    // The result set only holds the moved Idea if it's in the current tree
+    match destination_tree {
+        None => Ok(vec![moved_id]),
+        Some(name) => {
+            let path = repl.tree_mut(tree, &Some(name.clone()))?.get_path(moved_id)?;
+            repl.output().println(&format!("Moved to {}:{}", name, path));
+            Ok(vec![])
+        },
+    }
+}
+
+fn copy(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    let (source_tree, id_to_copy) = repl.select_in_any_tree(tree, &args[0])?;
+    let (destination_tree, parent_id) = repl.select_in_any_tree(tree, &args[1])?;
+    let new_name = args.get(2).cloned();
+
+    let copy_id = if source_tree == destination_tree {
+        repl.tree_mut(tree, &source_tree)?.copy_subtree(id_to_copy, parent_id, new_name)?
+    } else {
+        copy_between_trees(repl, tree, (&source_tree, id_to_copy), (&destination_tree, parent_id), new_name)?
+    };
+
+    // The result set only holds the copy if it's in the current tree
+    match destination_tree {
+        None => Ok(vec![copy_id]),
+        Some(_) => Ok(vec![]),
+    }
 }
 
-fn move_both_args(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
-    let id_to_move = repl.select_from_expression(tree, args[0].as_str())?;
-    let parent_id = repl.select_from_expression(tree, args[1].as_str())?;
+// Copy a subtree, with its tags and descriptions, from one open tree to
+// another. Each end is a tree name (None for the current tree) and an id.
+fn copy_between_trees(repl: &mut Repl, tree: &mut IdeaTree, source: (&Option<String>, i64), destination: (&Option<String>, i64), new_name: Option<String>) -> Result<i64> {
+    let mut outline = repl.tree_mut(tree, source.0)?.get_outline(source.1)?;
+    if let Some(new_name) = new_name {
+        outline.name = new_name;
+    }
 
-    tree.set_parent(id_to_move, parent_id)
+    Ok(repl.tree_mut(tree, destination.0)?.import_outlines(destination.1, vec![outline])?[0])
 }
 
-fn tag(repl: &mut Repl, tree: &mut IdeaTree, tags: Vec<String>) -> Result<()> {
-    tree.add_tags(repl.selected_id(), tags)
+fn new_from_template(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    let mut parts = args[0].splitn(2, ' ');
+    let (template, name) = match (parts.next(), parts.next().map(|name| name.trim())) {
+        (Some(template), Some(name)) if name.len() > 0 => (template, name),
+        _ => return Err(Error::DaVinci("Usage: new [template] [name]".to_string())),
+    };
+
+    let id = tree.instantiate_template(repl.selected_id(), template, name.to_string())?;
+    repl.run_command(tree, format!("select #{}", id));
+
+    Ok(vec![id])
 }
 
-fn tag_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
+fn list_templates(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
+    match tree.get_meta_idea(repl.selected_id(), "templates")? {
+        Some(templates) => {
+            for child_id in templates.child_ids {
+                let name = tree.get_name(child_id)?;
+                repl.output().println(&name);
+            }
+        },
+        None => repl.output().println("Add a .templates Idea, and its children can be used as templates for new Ideas"),
+    }
+    Ok(())
+}
+
+fn tag(repl: &mut Repl, tree: &mut IdeaTree, tags: Vec<String>) -> Result<Vec<i64>> {
+    tree.add_tags(repl.selected_id(), tags)?;
+
+    Ok(vec![repl.selected_id()])
+}
+
+fn tag_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<Vec<i64>> {
     let tags: Vec<String> = repl.prompt_for_args(vec!["tags?"])?[0].split(" ").map(|tag| tag.to_string()).collect();
 
     // Collect all the ids to tag without applying any,
@@ -218,47 +404,57 @@ fn tag_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Res
         ids_to_tag.push(repl.select_from_expression(tree, select_expression)?);
         Ok(true)
     }, false); // Don't store this input in history
-    for id_to_tag in ids_to_tag {
-        tree.add_tags(id_to_tag, tags.clone())?;
+    for id_to_tag in &ids_to_tag {
+        tree.add_tags(*id_to_tag, tags.clone())?;
     }
 
-    Ok(())
+    Ok(ids_to_tag)
 }
 
-fn untag(repl: &mut Repl, tree: &mut IdeaTree, tags: Vec<String>) -> Result<()> {
-    tree.remove_tags(repl.selected_id(), tags)
+fn untag(repl: &mut Repl, tree: &mut IdeaTree, tags: Vec<String>) -> Result<Vec<i64>> {
+    tree.remove_tags(repl.selected_id(), tags)?;
+
+    Ok(vec![repl.selected_id()])
 }
 
-fn cleartags(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
-    tree.clear_tags(repl.selected_id())
+fn cleartags(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<Vec<i64>> {
+    tree.clear_tags(repl.selected_id())?;
+
+    Ok(vec![repl.selected_id()])
 }
 
-fn list(repl: &Repl, tree: &IdeaTree, show_all: bool) -> Result<()> {
+fn list(repl: &mut Repl, tree: &IdeaTree, show_all: bool) -> Result<Vec<i64>> {
     let shown_child_ids = tree.get_child_ids(repl.selected_id(), false)?;
 
     for (child_idx, id) in shown_child_ids.iter().enumerate() {
         let child= tree.get_name_with_tags(*id)?;
 
-        println!("{}. {}", child_idx+1, child);
+        repl.output().println(&format!("{}. {}", child_idx+1, child));
     }
 
     if show_all {
         let mut hidden_child_ids = tree.get_child_ids(repl.selected_id(), true)?;
         hidden_child_ids.retain(|id| !shown_child_ids.contains(id));
 
-        for id in hidden_child_ids {
-            let child = tree.get_name_with_tags(id)?;
+        for id in &hidden_child_ids {
+            let child = tree.get_name_with_tags(*id)?;
 
-            println!("Hidden: {}", child);
+            repl.output().println(&format!("Hidden: {}", child));
         }
+
+        // Meta Ideas are listed, but not passed on, so `listall | delete`
+        // leaves the configuration alone
+        let mut child_ids = tree.get_child_ids(repl.selected_id(), true)?;
+        child_ids.retain(|id| !tree.get_name(*id).map(|name| name.starts_with('.')).unwrap_or(false));
+        return Ok(child_ids);
     }
 
-    Ok(())
+    Ok(shown_child_ids)
 }
 
 // TODO this is now ambiguous because get_name_with_tags() is already what's 
 // printed from `list`
-fn list_with_tags(repl: &Repl, tree: &IdeaTree, tags: Vec<String>) -> Result<()> {
+fn list_with_tags(repl: &mut Repl, tree: &IdeaTree, tags: Vec<String>) -> Result<()> {
 
 
     Ok(())
@@ -285,23 +481,24 @@ fn evaluate_add_expression(repl: &mut Repl, tree: &mut IdeaTree, expression: Str
     Ok((parent_id, name.to_string()))
 }
 
-fn add(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+fn add(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
     let (parent_id, name) = evaluate_add_expression(repl, tree, args[0].clone())?;
 
     let id = tree.create_idea(parent_id, name.to_string(), None)?;
     repl.run_command(tree, format!("select #{}", id));
 
-    Ok(())
+    Ok(vec![id])
 }
 
-fn add_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
+fn add_multiple(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<Vec<i64>> {
+    let mut new_ids = Vec::new();
     repl.prompt(" new idea ->", |ref mut repl, name: &str| {
         let (parent_id, name) = evaluate_add_expression(*repl, tree, name.to_string())?;
-        tree.create_idea(parent_id, name.to_string(), None)?;
+        new_ids.push(tree.create_idea(parent_id, name.to_string(), None)?);
         Ok(true)
     }, false); // Don't save Idea names in the command history
 
-    Ok(())
+    Ok(new_ids)
 }
 
 fn describe(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
@@ -309,18 +506,38 @@ fn describe(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<(
         Some(expression) => repl.select_from_expression(tree, &expression)?,
         None => repl.selected_id(),
     };
-    let existing_description = tree.get_description(target_id)?;
-
-    // TODO edit_rs get_input() seems not to work with rustyline
-    // let new_description = get_input(&existing_description)?;
-
-    println!("Description was: {}", existing_description);
-    let new_description = repl.prompt_for_args(vec!["description"])?[0].clone();
+    let idea = tree.get_idea(target_id)?;
+
+    // Other meta Ideas, like .templates, hold YAML if their description is
+    // YAML data already
+    let is_yaml = YAML_META_IDEAS.contains(&idea.name.as_str()) || (idea.name.starts_with('.') && match idea.get_yaml_data() {
+        Ok(Some(Yaml::Hash(_))) | Ok(Some(Yaml::Array(_))) => true,
+        _ => false,
+    });
+    let extension = if is_yaml { "yaml" } else { "md" };
+
+    let mut new_description = repl.edit_text("description", &idea.description, extension)?;
+
+    // Don't let a typo break the settings, shortcuts or macros of a subtree
+    if is_yaml {
+        while let Err(e) = YamlLoader::load_from_str(&new_description) {
+            repl.output().println(&format!("The description of {} must be valid YAML: {}", idea.name, e));
+
+            if repl.prompt_for_args(vec!["edit again? (y/n)"])?[0] != "y" {
+                repl.output().println("Description unchanged.");
+                return Ok(());
+            }
+            new_description = repl.edit_text("description", &new_description, extension)?;
+        }
+    }
 
-    if new_description != existing_description {
-        println!("Updating description.");
+    if new_description != idea.description {
+        repl.output().println("Updating description.");
         tree.set_description(target_id, &new_description)?
     }
+    else {
+        repl.output().println("Description unchanged.");
+    }
     Ok(())
 }
 
@@ -336,41 +553,278 @@ fn rename_selected(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> R
     let old_name = tree.get_name(repl.selected_id())?;
     let new_name = match args.into_iter().next() {
         Some(new_name) => new_name,
-        None => get_input(&old_name)?
+        None => repl.edit_text("name", &old_name, "txt")?
     };
 
     if old_name != new_name{
         // TODO this should have to run all other Idea name validations
         if new_name.contains('\n') || new_name.contains('\r') {
-            println!("Idea name cannot include newline");
+            repl.output().println("Idea name cannot include newline");
         }
         else {
-            println!("Updating name.");
+            repl.output().println("Updating name.");
             tree.set_name(repl.selected_id(), &new_name)?;
         }
     }
     else {
-        println!("Name unchanged.");
+        repl.output().println("Name unchanged.");
     }
 
     Ok(())
 }
 
-fn search(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+fn search(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
     let query = args.into_iter().next().unwrap();
 
     let matches = tree.search_ideas(&query)?;
 
     if matches.len() == 0 {
-        println!("No matches for query '{}'", query);
-    } else {
+        repl.output().println(&format!("No matches for query '{}'", query));
+    } else if !repl.is_piped() {
+        // When the matches are piped into another command, they don't need
+        // to be narrowed down to one
         repl.prompt_to_select_from(&matches, tree);
     }
 
-    Ok(())
+    Ok(matches.into_iter().map(|idea| idea.id).collect())
 }
 
 fn export(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
     let filename = args.into_iter().next().unwrap();
-    tree.export_idea(repl.selected_id(), &filename)
+    for e in tree.export_idea(repl.selected_id(), &filename)? {
+        match e {
+            Error::DaVinci(message) => repl.output().println(&format!("Skipped: {}", message)),
+            e => repl.output().println(&format!("Skipped: Error: {:?}", e)),
+        }
+    }
+    Ok(())
+}
+
+fn print_line_errors(repl: &mut Repl, label: &str, errors: Vec<(usize, Error)>) {
+    for (line, e) in errors {
+        match e {
+            Error::DaVinci(message) => repl.output().println(&format!("{} {}: {}", label, line, message)),
+            e => repl.output().println(&format!("{} {}: Error: {:?}", label, line, e)),
+        }
+    }
+}
+
+fn import(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    let mut filename = args[0].clone();
+    let parent_id = match args.get(1) {
+        Some(parent_expression) => repl.select_from_expression(tree, parent_expression)?,
+        None => repl.selected_id(),
+    };
+
+    let keep_ids = filename.ends_with(" --keep-ids");
+    if keep_ids {
+        let filename_length = filename.len() - " --keep-ids".len();
+        filename.truncate(filename_length);
+    }
+
+    // Completed tasks from a todo.txt file are merged into existing Ideas
+    if todotxt::is_todo_txt_file(&filename) {
+        let (done_ids, line_errors) = tree.merge_todo_txt(parent_id, &filename)?;
+        print_line_errors(repl, "Line", line_errors);
+        repl.output().println(&format!("Marked {} Idea(s) done from {}", done_ids.len(), filename));
+        return Ok(done_ids);
+    }
+
+    let is_table = filename.ends_with(".csv") || filename.ends_with(".tsv");
+
+    let new_ids = if keep_ids {
+        let (new_ids, errors) = tree.merge_json(parent_id, &filename)?;
+        for (_, e) in errors {
+            match e {
+                Error::DaVinci(message) => repl.output().println(&message),
+                e => repl.output().println(&format!("Error: {:?}", e)),
+            }
+        }
+        new_ids
+    } else if is_table {
+        // Rows that fail are reported one by one, and the rest are still imported
+        let (new_ids, row_errors) = tree.import_table(parent_id, &filename)?;
+        print_line_errors(repl, "Row", row_errors);
+        new_ids
+    } else {
+        tree.import_idea(parent_id, &filename)?
+    };
+    repl.output().println(&format!("Imported {} Idea(s) from {}", new_ids.len(), filename));
+
+    Ok(new_ids)
+}
+
+fn import_dir(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<Vec<i64>> {
+    let mut args = args.into_iter();
+    let mut options = DirImport::new(args.next().unwrap());
+
+    for option in args {
+        let mut parts = option.splitn(2, ' ');
+        let (flag, value) = (parts.next().unwrap(), parts.next().unwrap_or("").trim());
+        match flag {
+            "include" => options.include.push(value.to_string()),
+            "exclude" => options.exclude.push(value.to_string()),
+            "depth" => options.max_depth = Some(value.parse()?),
+            _ => return Err(Error::DaVinci(format!("import-dir has no option --{}", flag))),
+        }
+    }
+
+    let dir_id = tree.import_dir(repl.selected_id(), options)?;
+    repl.output().println(&format!("Imported directory into Idea #{}", dir_id));
+    Ok(vec![dir_id])
+}
+
+fn sync_dir(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<Vec<i64>> {
+    let dir_id = tree.sync_dir(repl.selected_id())?;
+    repl.output().println(&format!("Synced Idea #{} with its directory", dir_id));
+    Ok(vec![dir_id])
+}
+
+fn commit(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    let repo = match tree.path().and_then(GitRepo::containing) {
+        Some(repo) => repo,
+        None => return Err(Error::DaVinci("This tree isn't stored in a git repository".to_string())),
+    };
+
+    match repo.commit(tree, args.into_iter().next())? {
+        Some(message) => repl.output().println(&format!("Committed: {}", message.lines().next().unwrap_or(""))),
+        None => repl.output().println("Nothing to commit"),
+    }
+    Ok(())
+}
+
+fn open(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    let filename = &args[0];
+    // Opening a file that doesn't exist would create an empty tree
+    if !Path::new(filename).exists() {
+        return Err(Error::DaVinci(format!("{} doesn't exist", filename)));
+    }
+
+    // A tree that's open in another session can only be opened read-only
+    let opened = match live_session(Path::new(filename)) {
+        Some(holder) => {
+            let question = format!("{} is open in another session (process {}). Open it read-only? [y/N]", filename, holder.pid);
+            let answer = repl.prompt_for_args(vec![question.as_str()])?;
+            if answer[0].to_lowercase() != "y" {
+                return Ok(());
+            }
+            IdeaTree::open_read_only(filename)?
+        },
+        None => {
+            let mut opened = IdeaTree::open(filename)?;
+            opened.lock_session()?;
+            // A .dv file's text_mirror setting is left to the session which
+            // opened it first, but a tree loaded from a mirror is saved there
+            opened.write_back_to_text_mirror();
+            opened
+        },
+    };
+    let name = match args.get(1) {
+        Some(name) => name.clone(),
+        None => default_tree_name(&opened),
+    };
+    repl.mount_tree(tree, name.clone(), opened)?;
+    repl.output().println(&format!("Opened {} as '{}'. Select its Ideas with {}:[expression]", filename, name, name));
+    Ok(())
+}
+
+fn close(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    repl.unmount_tree(tree, &args[0])?;
+    Ok(())
+}
+
+fn list_trees(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
+    let current_name = repl.tree_name(tree);
+    for name in repl.tree_names(tree) {
+        let marker = if name == current_name { "*" } else { " " };
+        repl.output().println(&format!("{} {}", marker, name));
+    }
+    Ok(())
+}
+
+fn switch(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    repl.switch_tree(tree, &args[0])?;
+    repl.print(tree, true)
+}
+
+fn backup(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    if backup::back_up(tree)?.is_none() {
+        repl.output().println("Backups are turned off for this tree. Set backup_count in its .settings to turn them on.");
+    }
+    list_backups(repl, tree, args)
+}
+
+fn list_backups(repl: &mut Repl, tree: &mut IdeaTree, _args: Vec<String>) -> Result<()> {
+    let backups = backup::list_backups(tree)?;
+    if backups.len() == 0 {
+        repl.output().println("There are no backups of this tree");
+    }
+    for (idx, backup) in backups.iter().enumerate() {
+        repl.output().println(&format!("{}. {} ({})", idx + 1, backup.timestamp, backup.path.display()));
+    }
+    Ok(())
+}
+
+fn restore_backup(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    let backups = backup::list_backups(tree)?;
+    let number = args[0].parse::<usize>()?;
+    let chosen = match backups.get(number.wrapping_sub(1)) {
+        Some(backup) => backup,
+        None => {
+            repl.output().println(&format!("There is no backup number {}. Type restore-backup to list them.", number));
+            return Ok(());
+        },
+    };
+
+    // Backups are restored next to the tree unless a file is given
+    let destination = match args.get(1) {
+        Some(file) => Path::new(file).to_path_buf(),
+        None => {
+            let tree_path = tree.path()?;
+            let stem = tree_path.file_stem()?.to_string_lossy().into_owned();
+            tree_path.with_file_name(format!("{}-restored.dv", stem))
+        },
+    };
+
+    backup::restore_backup(chosen, &destination)?;
+    repl.output().println(&format!("Restored the backup from {} to {}. Type 'open {}' to look at it.", chosen.timestamp, destination.display(), destination.display()));
+    Ok(())
+}
+
+fn diff(repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>) -> Result<()> {
+    let mut args = args.into_iter();
+    let filename = args.next().unwrap();
+
+    let mut subtree_id = None;
+    let mut json = false;
+    for option in args {
+        let mut parts = option.splitn(2, ' ');
+        match (parts.next().unwrap(), parts.next().map(|value| value.trim())) {
+            ("under", Some(expression)) => subtree_id = Some(repl.select_from_expression(tree, expression)?),
+            ("json", None) => json = true,
+            _ => return Err(Error::DaVinci(format!("diff has no option --{}", option))),
+        }
+    }
+
+    // Anything that isn't a file is a revision of the tree in its git
+    // repository. Opening a file that doesn't exist would create an empty tree.
+    let old_tree = if Path::new(&filename).exists() {
+        IdeaTree::open_read_only(&filename)?
+    } else {
+        let repo = tree.path().and_then(|path| GitRepo::containing(path));
+        match (repo, tree.path()) {
+            (Some(repo), Some(tree_path)) => repo.tree_at_revision(tree_path, &filename)?,
+            _ => return Err(Error::DaVinci(format!("{} doesn't exist", filename))),
+        }
+    };
+
+    let changes = diff_trees(&old_tree, tree, subtree_id)?;
+    if json {
+        repl.output().println(&changes_to_json(&changes));
+    } else if changes.len() == 0 {
+        repl.output().println("No changes");
+    } else {
+        repl.output().print(&format_changes(&changes));
+    }
+    Ok(())
 }
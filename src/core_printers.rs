@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::str::from_utf8;
 
 use repl::IdeaPrinter;
+use output::OutputSink;
 use idea::{Idea, IdeaTree};
 use error::{Result, Error};
 use conv::prelude::*;
@@ -17,7 +18,7 @@ pub fn core_printers() -> HashMap<String, IdeaPrinter> {
     printers
 }
 
-fn progress(todo_idea: &Idea, tree: &IdeaTree) -> Result<f64> {
+pub fn progress(todo_idea: &Idea, tree: &IdeaTree) -> Result<f64> {
     // If no children are present, return has_tag(done)
     if todo_idea.child_ids.len() == 0 {
         if todo_idea.tags.contains(&"done".to_string()) {
@@ -57,11 +58,11 @@ fn progress(todo_idea: &Idea, tree: &IdeaTree) -> Result<f64> {
     }
 }
 
-fn print_progress_bar(todo_idea: &Idea, tree: &IdeaTree) -> Result<()> {
+fn print_progress_bar(todo_idea: &Idea, tree: &IdeaTree, output: &mut OutputSink) -> Result<()> {
     // [======>                       ]
     let progress = progress(todo_idea, tree)?;
 
-    print!("[");
+    output.print("[");
 
     let mut bar = String::new();
 
@@ -96,9 +97,9 @@ fn print_progress_bar(todo_idea: &Idea, tree: &IdeaTree) -> Result<()> {
         };
     }
 
-    print!("{}", bar);
+    output.print(&bar);
 
-    println!("]");
+    output.println("]");
 
     // TODO print a ___/___ number
 
@@ -0,0 +1,204 @@
+// Differences between two versions of a tree, matching Ideas by id. Every
+// change is reported with the Idea's path, in the new tree unless the Idea
+// was removed:
+//
+//    + Books/Dune (#6)
+//    - Music (#7)
+//    renamed Library (#5): Books -> Library
+//    moved Library/Emma (#8): Emma -> Library/Emma
+//    retagged Library (#5): +reading -todo
+//    redescribed Library (#5)
+//
+// An Idea which reused the id of a deleted one, the way the merge code tells
+// them apart, is reported as a removal and an addition instead of a rename.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::Value;
+
+use idea::{Idea, IdeaTree};
+use merge::reused_ids;
+use error::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Renamed,
+    Moved,
+    Retagged,
+    Redescribed,
+}
+
+impl ChangeKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::Moved => "moved",
+            ChangeKind::Retagged => "retagged",
+            ChangeKind::Redescribed => "redescribed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub id: i64,
+    pub kind: ChangeKind,
+    pub path: String,
+    // What changed, for renames and moves
+    pub old: Option<String>,
+    pub new: Option<String>,
+    // The tags before and after a tag change
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+}
+
+impl Change {
+    fn new(id: i64, kind: ChangeKind, path: String) -> Change {
+        Change { id, kind, path, old: None, new: None, old_tags: Vec::new(), new_tags: Vec::new() }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (old, new) = match self.kind {
+            ChangeKind::Retagged => (json!(self.old_tags), json!(self.new_tags)),
+            _ => (json!(self.old), json!(self.new)),
+        };
+        json!({
+            "id": self.id,
+            "change": self.kind.name(),
+            "path": self.path,
+            "old": old,
+            "new": new,
+        })
+    }
+}
+
+type IdeaMap = BTreeMap<i64, Idea>;
+
+fn idea_map(tree: &IdeaTree) -> Result<IdeaMap> {
+    Ok(tree.all_ideas()?.into_iter().map(|idea| (idea.id, idea)).collect())
+}
+
+fn path(ideas: &IdeaMap, id: i64) -> String {
+    let mut names = Vec::new();
+    let mut ancestor = ideas.get(&id);
+    while let Some(idea) = ancestor {
+        // The root Idea isn't part of paths
+        if idea.parent_id.is_none() {
+            break;
+        }
+        names.insert(0, idea.name.clone());
+        ancestor = idea.parent_id.and_then(|parent_id| ideas.get(&parent_id));
+    }
+    names.join("/")
+}
+
+fn subtree_ids(ideas: &IdeaMap, id: i64, subtree: &mut BTreeSet<i64>) {
+    if let Some(idea) = ideas.get(&id) {
+        subtree.insert(id);
+        for &child_id in &idea.child_ids {
+            subtree_ids(ideas, child_id, subtree);
+        }
+    }
+}
+
+// Compare an old version of a tree to a new one. With a subtree id, only
+// Ideas under it in either version are compared.
+pub fn diff_trees(old: &IdeaTree, new: &IdeaTree, subtree_id: Option<i64>) -> Result<Vec<Change>> {
+    let old = idea_map(old)?;
+    let new = idea_map(new)?;
+
+    let mut ids = BTreeSet::new();
+    match subtree_id {
+        Some(subtree_id) => {
+            subtree_ids(&old, subtree_id, &mut ids);
+            subtree_ids(&new, subtree_id, &mut ids);
+        },
+        None => ids.extend(old.keys().chain(new.keys()).cloned()),
+    }
+
+    let reused_ids = reused_ids(&old, &new);
+
+    let mut changes = Vec::new();
+    for id in ids {
+        let (old_idea, new_idea) = match (old.get(&id), new.get(&id)) {
+            (Some(_), Some(_)) if reused_ids.contains(&id) => {
+                changes.push(Change::new(id, ChangeKind::Removed, path(&old, id)));
+                changes.push(Change::new(id, ChangeKind::Added, path(&new, id)));
+                continue;
+            },
+            (Some(old_idea), Some(new_idea)) => (old_idea, new_idea),
+            (None, Some(_)) => {
+                changes.push(Change::new(id, ChangeKind::Added, path(&new, id)));
+                continue;
+            },
+            (Some(_), None) => {
+                changes.push(Change::new(id, ChangeKind::Removed, path(&old, id)));
+                continue;
+            },
+            (None, None) => continue,
+        };
+
+        let new_path = path(&new, id);
+        if old_idea.name != new_idea.name {
+            let mut change = Change::new(id, ChangeKind::Renamed, new_path.clone());
+            change.old = Some(old_idea.name.clone());
+            change.new = Some(new_idea.name.clone());
+            changes.push(change);
+        }
+        if old_idea.parent_id != new_idea.parent_id {
+            let mut change = Change::new(id, ChangeKind::Moved, new_path.clone());
+            change.old = Some(path(&old, id));
+            change.new = Some(new_path.clone());
+            changes.push(change);
+        }
+
+        // Tags are compared as sets, so reordering them isn't a change
+        let tags_changed = new_idea.tags.iter().any(|tag| !old_idea.tags.contains(tag))
+            || old_idea.tags.iter().any(|tag| !new_idea.tags.contains(tag));
+        if tags_changed {
+            let mut change = Change::new(id, ChangeKind::Retagged, new_path.clone());
+            change.old_tags = old_idea.tags.clone();
+            change.new_tags = new_idea.tags.clone();
+            changes.push(change);
+        }
+
+        if old_idea.description != new_idea.description {
+            changes.push(Change::new(id, ChangeKind::Redescribed, new_path));
+        }
+    }
+
+    Ok(changes)
+}
+
+pub fn format_changes(changes: &[Change]) -> String {
+    let mut text = String::new();
+
+    for change in changes {
+        text += &match change.kind {
+            ChangeKind::Added => format!("+ {} (#{})", change.path, change.id),
+            ChangeKind::Removed => format!("- {} (#{})", change.path, change.id),
+            ChangeKind::Retagged => {
+                let (old_tags, new_tags) = (&change.old_tags, &change.new_tags);
+                let mut tag_changes: Vec<String> = new_tags.iter().filter(|tag| !old_tags.contains(tag)).map(|tag| format!("+{}", tag)).collect();
+                tag_changes.extend(old_tags.iter().filter(|tag| !new_tags.contains(tag)).map(|tag| format!("-{}", tag)));
+                format!("retagged {} (#{}): {}", change.path, change.id, tag_changes.join(" "))
+            },
+            kind => match (change.old.as_ref(), change.new.as_ref()) {
+                (Some(old), Some(new)) => format!("{} {} (#{}): {} -> {}", kind.name(), change.path, change.id, old, new),
+                _ => format!("{} {} (#{})", kind.name(), change.path, change.id),
+            },
+        };
+        text += "\n";
+    }
+
+    text
+}
+
+pub fn changes_to_json(changes: &[Change]) -> String {
+    let changes: Vec<Value> = changes.iter().map(|change| change.to_json()).collect();
+    ::serde_json::to_string_pretty(&changes).expect("Serialization failed")
+}
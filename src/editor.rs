@@ -0,0 +1,97 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use error::{Result, Error};
+
+// Find the user's preferred editor the same way git does, preferring
+// $VISUAL over $EDITOR
+pub fn editor_command() -> Option<String> {
+    for variable in &["VISUAL", "EDITOR"] {
+        if let Ok(editor) = env::var(variable) {
+            if editor.trim().len() > 0 {
+                return Some(editor);
+            }
+        }
+    }
+
+    None
+}
+
+// Removes the temp file when dropped, so it goes whether editing succeeds or
+// fails partway
+struct TempFile {
+    path: PathBuf,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// Create a new temp file with a name nobody can guess ahead of time.
+// create_new refuses to open a file, or a symlink, that's already there, so
+// another user can't plant one to be written through.
+fn create_temp_file(extension: &str) -> Result<(TempFile, File)> {
+    loop {
+        // RandomState is seeded randomly, which is all the randomness the
+        // standard library offers
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(TEMP_FILES.fetch_add(1, Ordering::SeqCst));
+        let path = env::temp_dir().join(format!("davincibot-{}-{:016x}.{}", process::id(), hasher.finish(), extension));
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((TempFile { path }, file)),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Hand the terminal over to an editor to edit text in a temp file, whose
+// extension lets the editor pick the right syntax highlighting. Returns the
+// edited text once the editor exits.
+pub fn edit_text(editor: &str, text: &str, extension: &str) -> Result<String> {
+    let (temp_file, mut file) = create_temp_file(extension)?;
+
+    // Editors expect files to end with a newline, and add one if they don't,
+    // so one is added here and removed below to keep unchanged text unchanged
+    file.write_all(text.as_bytes())?;
+    file.write_all(b"\n")?;
+    drop(file);
+
+    run_editor(editor, &temp_file.path.to_string_lossy())?;
+
+    let mut edited_text = String::new();
+    File::open(&temp_file.path)?.read_to_string(&mut edited_text)?;
+
+    if edited_text.ends_with("\r\n") {
+        edited_text.pop();
+        edited_text.pop();
+    } else if edited_text.ends_with("\n") {
+        edited_text.pop();
+    }
+
+    Ok(edited_text)
+}
+
+fn run_editor(editor: &str, path: &str) -> Result<()> {
+    // The editor variable can include arguments, like "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next()?;
+
+    let status = Command::new(program).args(parts).arg(path).status()?;
+    if !status.success() {
+        return Err(Error::DaVinci(format!("Editor '{}' exited with {}", editor, status)));
+    }
+
+    Ok(())
+}
@@ -1,17 +1,21 @@
 extern crate std;
 extern crate rusqlite;
 extern crate yaml_rust;
-extern crate edit_rs;
+extern crate rustyline;
+extern crate serde_json;
+extern crate xml;
 
 #[derive(Debug)]
 pub enum Error {
     Rusqlite(rusqlite::Error),
     DaVinci(String),
     ParseInt(std::num::ParseIntError),
-    EditRS(edit_rs::Error),
     Utf8(std::str::Utf8Error),
     Yaml(yaml_rust::ScanError),
     IO(std::io::Error),
+    Readline(rustyline::error::ReadlineError),
+    Json(serde_json::Error),
+    Xml(xml::reader::Error),
     None(std::option::NoneError),
 }
 
@@ -27,6 +31,24 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<rustyline::error::ReadlineError> for Error {
+    fn from(e: rustyline::error::ReadlineError) -> Self {
+        self::Error::Readline(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        self::Error::Json(e)
+    }
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(e: xml::reader::Error) -> Self {
+        self::Error::Xml(e)
+    }
+}
+
 impl From<yaml_rust::ScanError> for Error {
     fn from(e: yaml_rust::ScanError) -> Self {
         self::Error::Yaml(e)
@@ -45,12 +67,6 @@ impl From<std::num::ParseIntError> for Error {
     }
 }
 
-impl From<edit_rs::Error> for Error {
-    fn from(e: edit_rs::Error) -> Self {
-        self::Error::EditRS(e)
-    }
-}
-
 impl From<std::str::Utf8Error> for Error {
     fn from(e: std::str::Utf8Error) -> Self {
         self::Error::Utf8(e)
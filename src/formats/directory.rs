@@ -0,0 +1,426 @@
+// A directory on disk is imported as an Idea with a child for each of its
+// subdirectories and files. The contents of Markdown and text files become
+// descriptions, and YAML front-matter at the top of a file becomes tags:
+//
+//    ---
+//    tags: [music, fun]
+//    due: 2018-11-01
+//    ---
+//
+// gives the tags music, fun and due:2018-11-01. Files and directories whose
+// names start with a dot are skipped, and so are directories which have
+// files, but none the include and exclude globs let through.
+//
+// The Idea for the imported directory gets a .import-dir meta child which
+// remembers the options, which Idea was created for each path, and the tags
+// each file's front-matter gave, so the directory can be synced again later
+// without duplicating anything:
+//
+//    path: /home/me/notes
+//    include: ["*.md"]
+//    exclude: [drafts]
+//    max_depth: 2
+//    ideas:
+//      songs: 14
+//      songs/guitar.md: 15
+//    front_matter:
+//      songs/guitar.md: [music, fun]
+//
+// Syncing replaces the tags from a file's old front-matter with its new ones,
+// and keeps tags added in Da Vinci Bot. The Ideas of files and directories
+// which are gone are deleted, unless Ideas were added under them in Da Vinci
+// Bot. Those are tagged missing instead, and untagged if the path comes back.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use yaml_rust::{Yaml, YamlLoader, YamlEmitter};
+use yaml_rust::yaml::Hash;
+
+use idea::IdeaTree;
+use error::{Result, Error};
+
+const META_NAME: &str = ".import-dir";
+const MISSING_TAG: &str = "missing";
+
+// The Idea imported for each path, and the tags from its front-matter
+type ImportedIdeas = BTreeMap<String, (i64, Vec<String>)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirImport {
+    pub path: String,
+    // Files are only imported if they match one of these globs, or if there are none
+    pub include: Vec<String>,
+    // Files and directories matching these globs are skipped
+    pub exclude: Vec<String>,
+    // How many levels of directories below the imported one to descend into
+    pub max_depth: Option<usize>,
+}
+
+impl DirImport {
+    pub fn new(path: String) -> DirImport {
+        DirImport {
+            path,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+        }
+    }
+
+    fn to_yaml(&self, ideas: &ImportedIdeas) -> Result<String> {
+        let strings = |strings: &Vec<String>| Yaml::Array(strings.iter().map(|string| Yaml::String(string.clone())).collect());
+
+        let mut hash = Hash::new();
+        hash.insert(Yaml::String("path".to_string()), Yaml::String(self.path.clone()));
+        hash.insert(Yaml::String("include".to_string()), strings(&self.include));
+        hash.insert(Yaml::String("exclude".to_string()), strings(&self.exclude));
+        if let Some(max_depth) = self.max_depth {
+            hash.insert(Yaml::String("max_depth".to_string()), Yaml::Integer(max_depth as i64));
+        }
+
+        let mut ideas_hash = Hash::new();
+        let mut front_matter_hash = Hash::new();
+        for (path, &(id, ref tags)) in ideas {
+            ideas_hash.insert(Yaml::String(path.clone()), Yaml::Integer(id));
+            if tags.len() > 0 {
+                front_matter_hash.insert(Yaml::String(path.clone()), strings(tags));
+            }
+        }
+        hash.insert(Yaml::String("ideas".to_string()), Yaml::Hash(ideas_hash));
+        hash.insert(Yaml::String("front_matter".to_string()), Yaml::Hash(front_matter_hash));
+
+        let mut yaml = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut yaml);
+            emitter.dump(&Yaml::Hash(hash)).map_err(|_| Error::DaVinci("Failed to write .import-dir record".to_string()))?;
+        }
+        Ok(yaml)
+    }
+
+    fn from_yaml(yaml: &str) -> Result<(DirImport, ImportedIdeas)> {
+        let record = YamlLoader::load_from_str(yaml)?.into_iter().next().unwrap_or(Yaml::BadValue);
+        let invalid = || Error::DaVinci(format!("The {} record is invalid", META_NAME));
+
+        let strings = |yaml: &Yaml| -> Vec<String> {
+            match yaml.as_vec() {
+                Some(strings) => strings.iter().filter_map(|string| string.as_str().map(|string| string.to_string())).collect(),
+                None => Vec::new(),
+            }
+        };
+
+        let options = DirImport {
+            path: record["path"].as_str().ok_or_else(|| invalid())?.to_string(),
+            include: strings(&record["include"]),
+            exclude: strings(&record["exclude"]),
+            max_depth: record["max_depth"].as_i64().map(|max_depth| max_depth as usize),
+        };
+
+        let mut ideas = BTreeMap::new();
+        if let Some(hash) = record["ideas"].as_hash() {
+            for (path, id) in hash.iter() {
+                match (path.as_str(), id.as_i64()) {
+                    (Some(path), Some(id)) => {
+                        let tags = strings(&record["front_matter"][path]);
+                        ideas.insert(path.to_string(), (id, tags));
+                    },
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+
+        Ok((options, ideas))
+    }
+}
+
+// Import a directory as a child of the given parent. If it was imported there
+// before, the Ideas from last time are updated instead. A child which wasn't
+// imported from the directory is never reused for it.
+pub fn import_dir(tree: &mut IdeaTree, parent_id: i64, mut options: DirImport) -> Result<i64> {
+    let path = fs::canonicalize(&options.path)?;
+    if !path.is_dir() {
+        return Err(Error::DaVinci(format!("{} is not a directory", options.path)));
+    }
+    options.path = path.to_string_lossy().to_string();
+
+    for child_id in tree.get_child_ids(parent_id, true)? {
+        if let Some(record_id) = tree.find_child(child_id, META_NAME)? {
+            let (previous_options, ideas) = DirImport::from_yaml(&tree.get_description(record_id)?)?;
+            if previous_options.path == options.path {
+                return sync(tree, child_id, options, ideas);
+            }
+        }
+    }
+
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or(options.path.clone());
+    let dir_id = tree.create_idea(parent_id, name, None)?;
+    sync(tree, dir_id, options, BTreeMap::new())
+}
+
+// Sync an Idea which was imported from a directory with the directory's
+// current contents, using the options it was imported with
+pub fn sync_dir(tree: &mut IdeaTree, dir_id: i64) -> Result<i64> {
+    let record_id = match tree.find_child(dir_id, META_NAME)? {
+        Some(record_id) => record_id,
+        None => return Err(Error::DaVinci(format!("Idea #{} wasn't imported from a directory", dir_id))),
+    };
+
+    let (options, ideas) = DirImport::from_yaml(&tree.get_description(record_id)?)?;
+    sync(tree, dir_id, options, ideas)
+}
+
+fn sync(tree: &mut IdeaTree, dir_id: i64, options: DirImport, old_ideas: ImportedIdeas) -> Result<i64> {
+    let path = PathBuf::from(&options.path);
+    let mut ideas = BTreeMap::new();
+    sync_entries(tree, &options, &old_ideas, &mut ideas, &path, "", dir_id, 0)?;
+
+    // Delete the Ideas of paths which are gone, files before the directories
+    // they were in
+    let imported_ids: Vec<i64> = old_ideas.values().chain(ideas.values()).map(|&(id, _)| id).collect();
+    for (path, &(id, ref tags)) in old_ideas.iter().rev() {
+        if ideas.contains_key(path) || tree.get_idea(id).is_err() {
+            continue;
+        }
+
+        let mut descendant_ids = Vec::new();
+        tree.collect_descendant_ids(id, &mut descendant_ids)?;
+        if descendant_ids.iter().all(|descendant_id| imported_ids.contains(descendant_id)) {
+            tree.delete_subtree(id)?;
+        } else {
+            if !tree.get_tags(id, false)?.contains(&MISSING_TAG.to_string()) {
+                tree.add_tags(id, vec![MISSING_TAG.to_string()])?;
+            }
+            ideas.insert(path.clone(), (id, tags.clone()));
+        }
+    }
+
+    let record = options.to_yaml(&ideas)?;
+    let record_id = match tree.find_child(dir_id, META_NAME)? {
+        Some(record_id) => record_id,
+        None => tree.create_idea(dir_id, META_NAME.to_string(), None)?,
+    };
+    tree.set_description(record_id, &record)?;
+
+    Ok(dir_id)
+}
+
+fn sync_entries(tree: &mut IdeaTree, options: &DirImport, old_ideas: &ImportedIdeas, ideas: &mut ImportedIdeas, dir: &Path, relative_dir: &str, parent_id: i64, depth: usize) -> Result<()> {
+    if let Some(max_depth) = options.max_depth {
+        if depth >= max_depth {
+            return Ok(());
+        }
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+
+    for entry in entries {
+        let name = match entry.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if name.starts_with(".") {
+            continue;
+        }
+
+        let relative_path = if relative_dir.len() > 0 { format!("{}/{}", relative_dir, name) } else { name.clone() };
+        if options.exclude.iter().any(|glob| glob_matches_path(glob, &relative_path)) {
+            continue;
+        }
+
+        if entry.is_dir() {
+            if !has_entries_to_import(options, &entry, &relative_path, depth + 1)? {
+                continue;
+            }
+            let id = sync_idea(tree, old_ideas, ideas, &relative_path, parent_id, name, None)?;
+            sync_entries(tree, options, old_ideas, ideas, &entry, &relative_path, id, depth + 1)?;
+        } else {
+            if !is_included(options, &relative_path) {
+                continue;
+            }
+
+            let contents = if is_text_file(&name) {
+                Some(String::from_utf8_lossy(&fs::read(&entry)?).to_string())
+            } else {
+                None
+            };
+            sync_idea(tree, old_ideas, ideas, &relative_path, parent_id, name, contents)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_included(options: &DirImport, relative_path: &str) -> bool {
+    options.include.is_empty() || options.include.iter().any(|glob| glob_matches_path(glob, relative_path))
+}
+
+// Whether a directory is empty (apart from dot files), or has a file or directory in it which would
+// be imported. A directory too deep to descend into is imported as it is.
+fn has_entries_to_import(options: &DirImport, dir: &Path, relative_dir: &str, depth: usize) -> Result<bool> {
+    if options.max_depth.map_or(false, |max_depth| depth >= max_depth) {
+        return Ok(true);
+    }
+
+    let mut empty = true;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?.path();
+        let name = match entry.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if name.starts_with(".") {
+            continue;
+        }
+        empty = false;
+
+        let relative_path = format!("{}/{}", relative_dir, name);
+        if options.exclude.iter().any(|glob| glob_matches_path(glob, &relative_path)) {
+            continue;
+        }
+        if entry.is_dir() {
+            if has_entries_to_import(options, &entry, &relative_path, depth + 1)? {
+                return Ok(true);
+            }
+        } else if is_included(options, &relative_path) {
+            return Ok(true);
+        }
+    }
+
+    Ok(empty)
+}
+
+// Update the Idea for a path, or create it if it was never imported or has
+// been deleted since
+fn sync_idea(tree: &mut IdeaTree, old_ideas: &ImportedIdeas, ideas: &mut ImportedIdeas, relative_path: &str, parent_id: i64, name: String, contents: Option<String>) -> Result<i64> {
+    let (id, old_front_matter) = match old_ideas.get(relative_path) {
+        Some(&(id, ref tags)) if tree.get_idea(id).is_ok() => (id, tags.clone()),
+        _ => (tree.create_idea(parent_id, name, None)?, Vec::new()),
+    };
+    // The path is back after it went missing
+    if tree.get_tags(id, false)?.contains(&MISSING_TAG.to_string()) {
+        tree.remove_tags(id, vec![MISSING_TAG.to_string()])?;
+    }
+
+    let mut front_matter = Vec::new();
+    if let Some(contents) = contents {
+        let (tags, description) = split_front_matter(&contents);
+        tree.set_description(id, &description)?;
+
+        // Tags added in Da Vinci Bot are kept
+        let current_tags = tree.get_tags(id, false)?;
+        let removed_tags: Vec<String> = old_front_matter.into_iter().filter(|tag| !tags.contains(tag) && current_tags.contains(tag)).collect();
+        if removed_tags.len() > 0 {
+            tree.remove_tags(id, removed_tags)?;
+        }
+        let new_tags: Vec<String> = tags.iter().filter(|tag| !current_tags.contains(tag)).cloned().collect();
+        if new_tags.len() > 0 {
+            tree.add_tags(id, new_tags)?;
+        }
+        front_matter = tags;
+    }
+
+    ideas.insert(relative_path.to_string(), (id, front_matter));
+    Ok(id)
+}
+
+fn is_text_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".md") || name.ends_with(".markdown") || name.ends_with(".txt")
+}
+
+// Separate YAML front-matter from the rest of a file, turning it into tags.
+// The tags key lists plain tags, and every other key with a plain value
+// becomes a key:value tag.
+pub fn split_front_matter(contents: &str) -> (Vec<String>, String) {
+    let contents = contents.replace("\r\n", "\n");
+    let no_front_matter = (Vec::new(), contents.clone());
+
+    if !contents.starts_with("---\n") {
+        return no_front_matter;
+    }
+    let end = match contents[4..].find("\n---") {
+        Some(end) => end + 4,
+        None => return no_front_matter,
+    };
+    let front_matter = match YamlLoader::load_from_str(&contents[4..end]) {
+        Ok(mut documents) => match documents.pop() {
+            Some(Yaml::Hash(hash)) => hash,
+            _ => return no_front_matter,
+        },
+        Err(_) => return no_front_matter,
+    };
+
+    let mut tags = Vec::new();
+    for (key, value) in front_matter.iter() {
+        let key = match key.as_str() {
+            Some(key) => key,
+            None => continue,
+        };
+
+        if key == "tags" {
+            match *value {
+                Yaml::Array(ref values) => tags.extend(values.iter().filter_map(yaml_scalar)),
+                Yaml::String(ref values) => tags.extend(values.split(|c: char| c == ',' || c.is_whitespace()).filter(|tag| tag.len() > 0).map(|tag| tag.to_string())),
+                _ => { },
+            }
+        } else if let Some(value) = yaml_scalar(value) {
+            tags.push(format!("{}:{}", key, value));
+        }
+    }
+
+    // Skip the rest of the closing --- line
+    let body = match contents[end + 4..].find('\n') {
+        Some(newline) => &contents[end + 4 + newline + 1..],
+        None => "",
+    };
+
+    (tags, body.trim_left_matches('\n').to_string())
+}
+
+fn yaml_scalar(yaml: &Yaml) -> Option<String> {
+    match *yaml {
+        Yaml::String(ref value) => Some(value.clone()),
+        Yaml::Integer(value) => Some(value.to_string()),
+        Yaml::Real(ref value) => Some(value.clone()),
+        Yaml::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+// Globs containing a slash match the whole path relative to the imported
+// directory. Others only have to match the file or directory name.
+fn glob_matches_path(glob: &str, relative_path: &str) -> bool {
+    if glob.contains('/') {
+        glob_matches(glob, relative_path)
+    } else {
+        glob_matches(glob, relative_path.rsplit('/').next().unwrap_or(relative_path))
+    }
+}
+
+// * matches anything but a slash, ** matches anything, and ? matches one
+// character
+pub fn glob_matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_matches_chars(&glob, &text)
+}
+
+fn glob_matches_chars(glob: &[char], text: &[char]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some(&'*') if glob.get(1) == Some(&'*') => {
+            (0..text.len() + 1).any(|skip| glob_matches_chars(&glob[2..], &text[skip..]))
+        },
+        Some(&'*') => {
+            (0..text.len() + 1)
+                .take_while(|&skip| skip == 0 || text[skip - 1] != '/')
+                .any(|skip| glob_matches_chars(&glob[1..], &text[skip..]))
+        },
+        Some(&'?') => !text.is_empty() && text[0] != '/' && glob_matches_chars(&glob[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && glob_matches_chars(&glob[1..], &text[1..]),
+    }
+}
@@ -0,0 +1,87 @@
+// Ideas are exported to Graphviz DOT as a directed graph from each Idea to
+// its children, which `dot -Tsvg` and friends can draw. These settings of the
+// exported Idea control the diagram:
+//
+//    dot_max_depth: 3          # Leave out Ideas deeper than this
+//    dot_hide_ignored: false   # Include children which `list` hides
+//    dot_colors:               # Fill the nodes of Ideas with these tags
+//      done: gray
+//      music: lightblue
+
+use std::collections::HashMap;
+
+use yaml_rust::Yaml;
+
+use idea::IdeaTree;
+use error::{Result, Error};
+
+pub fn to_dot(tree: &IdeaTree, id: i64) -> Result<String> {
+    let max_depth = match tree.get_setting(id, "dot_max_depth")? {
+        Yaml::BadValue => None,
+        Yaml::Integer(max_depth) => Some(max_depth as usize),
+        _ => return Err(Error::DaVinci("dot_max_depth setting is not set to an integer!".to_string())),
+    };
+
+    // Like `list`, hide ignored children unless asked not to
+    let include_hidden = match tree.get_setting(id, "dot_hide_ignored")? {
+        Yaml::BadValue => false,
+        Yaml::Boolean(hide_ignored) => !hide_ignored,
+        _ => return Err(Error::DaVinci("dot_hide_ignored setting is not set to true or false!".to_string())),
+    };
+
+    let mut colors = HashMap::new();
+    match tree.get_setting(id, "dot_colors")? {
+        Yaml::BadValue => { },
+        Yaml::Hash(hash) => {
+            for (tag, color) in hash.iter() {
+                match (tag.as_str(), color.as_str()) {
+                    (Some(tag), Some(color)) => { colors.insert(tag.to_string(), color.to_string()); },
+                    _ => return Err(Error::DaVinci("dot_colors setting must map tags to color names!".to_string())),
+                }
+            }
+        },
+        _ => return Err(Error::DaVinci("dot_colors setting must map tags to color names!".to_string())),
+    }
+
+    let mut dot = String::new();
+    dot += "digraph ideas {\n";
+    dot += "    node [shape=box];\n";
+    write_idea(&mut dot, tree, id, 0, &DotOptions { max_depth, include_hidden, colors })?;
+    dot += "}\n";
+
+    Ok(dot)
+}
+
+struct DotOptions {
+    max_depth: Option<usize>,
+    include_hidden: bool,
+    colors: HashMap<String, String>,
+}
+
+fn write_idea(dot: &mut String, tree: &IdeaTree, id: i64, depth: usize, options: &DotOptions) -> Result<()> {
+    let idea = tree.get_idea(id)?;
+
+    *dot += &format!("    idea{} [label=\"{}\"", id, escape(&idea.format_name_with_tags()));
+    for tag in &idea.tags {
+        if let Some(color) = options.colors.get(tag) {
+            *dot += &format!(", style=filled, fillcolor=\"{}\"", escape(color));
+            break;
+        }
+    }
+    *dot += "];\n";
+
+    if options.max_depth.map(|max_depth| depth >= max_depth).unwrap_or(false) {
+        return Ok(());
+    }
+
+    for child_id in tree.get_child_ids(id, options.include_hidden)? {
+        *dot += &format!("    idea{} -> idea{};\n", id, child_id);
+        write_idea(dot, tree, child_id, depth + 1, options)?;
+    }
+
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+}
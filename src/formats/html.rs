@@ -0,0 +1,111 @@
+// Ideas are exported to a single HTML file which needs no other files or
+// network access to display. Each Idea is a collapsible <details> element,
+// with tags as badges, the description rendered from Markdown, and a progress
+// bar for Ideas tagged todo. Meta Ideas are left out.
+//
+// Raw HTML in descriptions is escaped instead of passed through, images from
+// other servers are replaced by their alt text, and links which aren't http,
+// https, mailto or relative are replaced by their text, so the file can't run
+// scripts or load anything over the network.
+
+use pulldown_cmark::{Parser, Event, Tag};
+use pulldown_cmark::html::push_html;
+
+use idea::IdeaTree;
+use core_printers::progress;
+use error::Result;
+
+const STYLE: &'static str = "
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; color: #222; }
+details { margin: 0.3em 0 0.3em 1.2em; }
+details > details, details > .leaf { margin-left: 1.5em; }
+summary { cursor: pointer; font-weight: bold; }
+.leaf { margin: 0.3em 0 0.3em 2.4em; font-weight: bold; }
+.tag { display: inline-block; margin-left: 0.4em; padding: 0 0.5em; border-radius: 0.8em; background: #dde; font-size: 0.8em; font-weight: normal; }
+.progress { margin-left: 0.6em; font-size: 0.8em; font-weight: normal; }
+.description { margin-left: 1.2em; font-weight: normal; }
+";
+
+pub fn to_html(tree: &IdeaTree, id: i64) -> Result<String> {
+    let title = escape(&tree.get_name(id)?);
+
+    let mut html = String::new();
+    html += "<!DOCTYPE html>\n";
+    html += "<html>\n<head>\n<meta charset=\"utf-8\">\n";
+    html += &format!("<title>{}</title>\n", title);
+    html += &format!("<style>{}</style>\n", STYLE);
+    html += "</head>\n<body>\n";
+    write_idea(&mut html, tree, id)?;
+    html += "</body>\n</html>\n";
+
+    Ok(html)
+}
+
+fn write_idea(html: &mut String, tree: &IdeaTree, id: i64) -> Result<()> {
+    let idea = tree.get_idea(id)?;
+
+    let mut heading = escape(&idea.name);
+    for tag in &idea.tags {
+        heading += &format!("<span class=\"tag\">{}</span>", escape(tag));
+    }
+    // Progress is shown wherever the terminal would print a progress bar
+    if tree.get_tags(id, true)?.contains(&"todo".to_string()) {
+        let progress = progress(&idea, tree)?;
+        heading += &format!("<span class=\"progress\"><progress value=\"{:.3}\" max=\"1\"></progress> {:.0}%</span>", progress, progress * 100f64);
+    }
+
+    let mut child_ids = idea.child_ids.clone();
+    child_ids.retain(|child_id| !tree.get_name(*child_id).map(|name| name.starts_with('.')).unwrap_or(false));
+
+    if idea.description.len() == 0 && child_ids.len() == 0 {
+        *html += &format!("<div class=\"leaf\">{}</div>\n", heading);
+        return Ok(());
+    }
+
+    *html += &format!("<details open>\n<summary>{}</summary>\n", heading);
+    if idea.description.len() > 0 {
+        *html += "<div class=\"description\">\n";
+        push_html(html, sanitize(Parser::new(&idea.description)));
+        *html += "</div>\n";
+    }
+    for child_id in child_ids {
+        write_idea(html, tree, child_id)?;
+    }
+    *html += "</details>\n";
+
+    Ok(())
+}
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("//") || url.contains("://")
+}
+
+// Links with a scheme other than http, https or mailto, like javascript: or
+// data:, could run scripts when clicked. Relative links and #fragments are
+// fine.
+fn is_safe_link(url: &str) -> bool {
+    let url = url.trim_left_matches(|c: char| c.is_whitespace() || c.is_control());
+    match url.find(|c: char| c == ':' || c == '/' || c == '?' || c == '#') {
+        Some(index) if url[index..].starts_with(':') => {
+            let scheme = url[..index].to_lowercase();
+            scheme == "http" || scheme == "https" || scheme == "mailto"
+        },
+        _ => true,
+    }
+}
+
+fn sanitize<'a, I: Iterator<Item = Event<'a>>>(events: I) -> impl Iterator<Item = Event<'a>> {
+    events.filter_map(|event| match event {
+        // The raw HTML becomes text, which push_html escapes
+        Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+        // Leaving out a remote image's tags leaves its alt text
+        Event::Start(Tag::Image(ref url, _)) | Event::End(Tag::Image(ref url, _)) if is_remote(url) => None,
+        // Leaving out an unsafe link's tags leaves its text
+        Event::Start(Tag::Link(ref url, _)) | Event::End(Tag::Link(ref url, _)) if !is_safe_link(url) => None,
+        event => Some(event),
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;")
+}
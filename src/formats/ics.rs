@@ -0,0 +1,141 @@
+// Dated Ideas in a subtree are exported to an iCalendar file. Ideas with a
+// due date become to-dos (VTODO), and Ideas which are only scheduled become
+// all-day events (VEVENT):
+//
+//    BEGIN:VTODO
+//    UID:idea-12.chores@davincibot
+//    SUMMARY:Return library books
+//    DUE;VALUE=DATE:20181101
+//    CATEGORIES:errands
+//    END:VTODO
+//
+// UIDs come from Idea ids and the name of the tree's file, so importing a new
+// export into a calendar updates the entries from the last one instead of
+// duplicating them, and exports of different trees don't overwrite each
+// other's entries. Ideas with dates that aren't in YYYY-MM-DD format are left
+// out, and returned as errors.
+
+use idea::{Idea, IdeaTree, is_date_tag, utc_now};
+use error::{Result, Error};
+
+pub fn to_ics(tree: &IdeaTree, id: i64) -> Result<(String, Vec<Error>)> {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Da Vinci Bot//Da Vinci Bot//EN".to_string(),
+    ];
+    let tree_name = tree.path()
+        .and_then(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or("memory".to_string());
+    let mut skipped = Vec::new();
+    write_idea(&mut lines, &mut skipped, tree, id, &escape_text(&tree_name), &utc_timestamp())?;
+    lines.push("END:VCALENDAR".to_string());
+
+    // iCalendar lines end with CRLF and are folded to 75 bytes
+    let mut ics = String::new();
+    for line in lines {
+        ics += &fold_line(&line);
+    }
+    Ok((ics, skipped))
+}
+
+fn write_idea(lines: &mut Vec<String>, skipped: &mut Vec<Error>, tree: &IdeaTree, id: i64, tree_name: &str, timestamp: &str) -> Result<()> {
+    let idea = tree.get_idea(id)?;
+
+    // Meta Ideas aren't on the calendar, and neither are their children
+    if idea.name.starts_with(".") {
+        return Ok(());
+    }
+
+    let dates = date_value(&idea, "due").and_then(|due| Ok((due, date_value(&idea, "scheduled")?)));
+    let (due, scheduled) = match dates {
+        Ok(dates) => dates,
+        Err(e) => {
+            skipped.push(e);
+            (None, None)
+        },
+    };
+    if due.is_some() || scheduled.is_some() {
+        let component = if due.is_some() { "VTODO" } else { "VEVENT" };
+
+        lines.push(format!("BEGIN:{}", component));
+        lines.push(format!("UID:idea-{}.{}@davincibot", idea.id, tree_name));
+        lines.push(format!("DTSTAMP:{}", timestamp));
+        lines.push(format!("SUMMARY:{}", escape_text(&idea.name)));
+        if let Some(scheduled) = scheduled {
+            lines.push(format!("DTSTART;VALUE=DATE:{}", scheduled));
+        }
+        if let Some(due) = due {
+            lines.push(format!("DUE;VALUE=DATE:{}", due));
+            if idea.tags.contains(&"done".to_string()) {
+                lines.push("STATUS:COMPLETED".to_string());
+            }
+        }
+        if idea.description.len() > 0 {
+            lines.push(format!("DESCRIPTION:{}", escape_text(&idea.description)));
+        }
+
+        let categories: Vec<String> = idea.tags.iter()
+            .filter(|tag| !is_date_tag(tag))
+            .map(|tag| escape_text(tag))
+            .collect();
+        if categories.len() > 0 {
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+        }
+        lines.push(format!("END:{}", component));
+    }
+
+    for child_id in idea.child_ids {
+        write_idea(lines, skipped, tree, child_id, tree_name, timestamp)?;
+    }
+
+    Ok(())
+}
+
+// Convert a YYYY-MM-DD date to an iCalendar DATE value
+fn date_value(idea: &Idea, key: &str) -> Result<Option<String>> {
+    let date = match idea.get_date(key) {
+        Some(date) => date,
+        None => return Ok(None),
+    };
+
+    let is_valid = date.len() == 10 && date.chars().enumerate().all(|(idx, c)| match idx {
+        4 | 7 => c == '-',
+        _ => c.is_digit(10),
+    });
+    if !is_valid {
+        return Err(Error::DaVinci(format!("Idea #{} has a {} date that isn't in YYYY-MM-DD format: {}", idea.id, key, date)));
+    }
+
+    Ok(Some(date.replace("-", "")))
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace("\\", "\\\\").replace(";", "\\;").replace(",", "\\,").replace("\r\n", "\\n").replace("\n", "\\n")
+}
+
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_length = 0;
+
+    for c in line.chars() {
+        if line_length + c.len_utf8() > 75 {
+            // Continuation lines start with a space, which counts toward their length
+            folded += "\r\n ";
+            line_length = 1;
+        }
+        folded.push(c);
+        line_length += c.len_utf8();
+    }
+
+    folded += "\r\n";
+    folded
+}
+
+// The current UTC time in iCalendar's basic format, like 20181030T120000Z
+fn utc_timestamp() -> String {
+    let (year, month, day, seconds_of_day) = utc_now();
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day,
+        seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60)
+}
@@ -0,0 +1,171 @@
+// Ideas are exported to JSON for other tools to read, in a versioned format
+// described by schema/davincibot-tree.schema.json:
+//
+//    {
+//      "format": "davincibot",
+//      "version": 1,
+//      "root": {
+//        "id": 5,
+//        "name": "Books",
+//        "tags": ["reading"],
+//        "description": "max_description: 300",
+//        "data": { "max_description": 300 },
+//        "children": [ ... ]
+//      }
+//    }
+//
+// data holds the description parsed as YAML when it is a mapping or a list,
+// and is null otherwise. It is only for other tools to read, and is ignored
+// when importing.
+
+use serde_json;
+use serde_json::{Value, Map, Number};
+use yaml_rust::Yaml;
+
+use formats::IdeaOutline;
+use idea::IdeaTree;
+use error::{Result, Error};
+
+pub const FORMAT_NAME: &'static str = "davincibot";
+pub const FORMAT_VERSION: i64 = 1;
+
+// An Idea read from JSON, which may carry the id it was exported with
+#[derive(Debug)]
+pub struct JsonIdea {
+    pub id: Option<i64>,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub description: String,
+    pub children: Vec<JsonIdea>,
+}
+
+impl JsonIdea {
+    pub fn into_outline(self) -> IdeaOutline {
+        IdeaOutline {
+            name: self.name,
+            tags: self.tags,
+            description: self.description,
+            children: self.children.into_iter().map(|child| child.into_outline()).collect(),
+        }
+    }
+}
+
+pub fn to_json(tree: &IdeaTree, id: i64) -> Result<String> {
+    let document = json!({
+        "format": FORMAT_NAME,
+        "version": FORMAT_VERSION,
+        "root": idea_to_json(tree, id)?,
+    });
+
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
+
+fn idea_to_json(tree: &IdeaTree, id: i64) -> Result<Value> {
+    let idea = tree.get_idea(id)?;
+
+    let data = match idea.get_yaml_data() {
+        Ok(Some(yaml)) => match yaml {
+            Yaml::Hash(_) | Yaml::Array(_) => yaml_to_json(&yaml),
+            _ => Value::Null,
+        },
+        _ => Value::Null,
+    };
+
+    let mut children = Vec::new();
+    for child_id in &idea.child_ids {
+        children.push(idea_to_json(tree, *child_id)?);
+    }
+
+    Ok(json!({
+        "id": idea.id,
+        "name": idea.name,
+        "tags": idea.tags,
+        "description": idea.description,
+        "data": data,
+        "children": children,
+    }))
+}
+
+fn yaml_to_json(yaml: &Yaml) -> Value {
+    match yaml {
+        Yaml::Real(real) => match real.parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(number) => Value::Number(number),
+            None => Value::Null,
+        },
+        Yaml::Integer(integer) => Value::from(*integer),
+        Yaml::String(string) => Value::String(string.clone()),
+        Yaml::Boolean(boolean) => Value::Bool(*boolean),
+        Yaml::Array(array) => Value::Array(array.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(hash) => {
+            let mut object = Map::new();
+            for (key, value) in hash {
+                // JSON only has string keys
+                let key = match key {
+                    Yaml::String(key) => key.clone(),
+                    Yaml::Real(key) => key.clone(),
+                    Yaml::Integer(key) => key.to_string(),
+                    Yaml::Boolean(key) => key.to_string(),
+                    _ => continue,
+                };
+                object.insert(key, yaml_to_json(value));
+            }
+            Value::Object(object)
+        },
+        _ => Value::Null,
+    }
+}
+
+pub fn from_json(text: &str) -> Result<JsonIdea> {
+    let document: Value = serde_json::from_str(text)?;
+
+    if document["format"] != FORMAT_NAME {
+        return Err(invalid("format is not \"davincibot\""));
+    }
+    match document["version"].as_i64() {
+        Some(version) if version <= FORMAT_VERSION => { },
+        Some(version) => return Err(Error::DaVinci(format!("JSON format version {} is newer than this version of Da Vinci Bot supports ({})", version, FORMAT_VERSION))),
+        None => return Err(invalid("version is missing")),
+    }
+
+    json_to_idea(&document["root"])
+}
+
+fn json_to_idea(value: &Value) -> Result<JsonIdea> {
+    let name = match value["name"].as_str() {
+        Some(name) => name.to_string(),
+        None => return Err(invalid("an Idea has no name")),
+    };
+
+    let id = match &value["id"] {
+        Value::Null => None,
+        id => match id.as_i64() {
+            Some(id) => Some(id),
+            None => return Err(invalid(&format!("the id of '{}' is not an integer", name))),
+        },
+    };
+
+    let mut tags = Vec::new();
+    if let Some(tag_values) = value["tags"].as_array() {
+        for tag in tag_values {
+            match tag.as_str() {
+                Some(tag) => tags.push(tag.to_string()),
+                None => return Err(invalid(&format!("a tag of '{}' is not a string", name))),
+            }
+        }
+    }
+
+    let description = value["description"].as_str().unwrap_or("").to_string();
+
+    let mut children = Vec::new();
+    if let Some(child_values) = value["children"].as_array() {
+        for child in child_values {
+            children.push(json_to_idea(child)?);
+        }
+    }
+
+    Ok(JsonIdea { id, name, tags, description, children })
+}
+
+fn invalid(problem: &str) -> Error {
+    Error::DaVinci(format!("Invalid Da Vinci Bot JSON: {}", problem))
+}
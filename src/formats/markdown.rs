@@ -0,0 +1,237 @@
+// Ideas are exported to Markdown as headings, with the depth of the heading
+// matching the depth of the Idea in the exported subtree:
+//
+//    # Books #reading
+//
+//    Description of the Books Idea, verbatim.
+//
+//    ## Dune #scifi #done
+//
+// Headings are not capped at Markdown's 6 levels, so deeper Ideas keep their
+// depth when imported again. Lines of a description (and words of a name)
+// which would be read as a heading (or a tag) are escaped with a backslash,
+// except inside ``` or ~~~ code blocks. A description with a code block that
+// is never closed has its fence lines escaped too, so the block can't swallow
+// the headings after it.
+
+use formats::{IdeaOutline, split_lines};
+use error::{Result, Error};
+
+pub fn to_markdown(outline: &IdeaOutline) -> String {
+    let mut markdown = String::new();
+    write_outline(&mut markdown, outline, 1);
+    markdown
+}
+
+fn write_outline(markdown: &mut String, outline: &IdeaOutline, depth: usize) {
+    let name_words: Vec<String> = outline.name.split(' ').map(|word| escape(word, is_tag_like)).collect();
+
+    *markdown += &"#".repeat(depth);
+    *markdown += " ";
+    *markdown += &name_words.join(" ");
+    for tag in &outline.tags {
+        *markdown += &format!(" #{}", tag);
+    }
+    *markdown += "\n\n";
+
+    if outline.description.len() > 0 {
+        let lines: Vec<&str> = outline.description.split('\n').collect();
+        let fenced = fenced_lines(&lines);
+        for (idx, line) in lines.iter().enumerate() {
+            if fenced[idx] {
+                *markdown += line;
+            } else {
+                *markdown += &escape(line, is_markup_line);
+            }
+            *markdown += "\n";
+        }
+        *markdown += "\n";
+    }
+
+    for child in &outline.children {
+        write_outline(markdown, child, depth + 1);
+    }
+}
+
+// Every heading in the Markdown becomes an Idea, nested under the nearest
+// shallower heading above it. Text before the first heading is ignored.
+pub fn from_markdown(markdown: &str) -> Result<Vec<IdeaOutline>> {
+    let mut roots = Vec::new();
+
+    // Ideas which can still receive description lines and children, with
+    // their depth and the lines of their body
+    let mut open_outlines: Vec<(usize, IdeaOutline, Vec<&str>)> = Vec::new();
+    let mut fence = None;
+
+    for line in split_lines(markdown) {
+        let (next_fence, in_fence) = after_line(fence, line);
+        fence = next_fence;
+        // A # line in a code block isn't a heading
+        let depth = if in_fence { None } else { heading_depth(line) };
+        match depth {
+            Some(depth) => {
+                close_outlines(&mut open_outlines, &mut roots, depth);
+                open_outlines.push((depth, parse_heading(&line[depth..])?, Vec::new()));
+            },
+            None => {
+                if let Some(open_outline) = open_outlines.last_mut() {
+                    open_outline.2.push(line);
+                }
+            },
+        }
+    }
+    close_outlines(&mut open_outlines, &mut roots, 0);
+
+    if roots.len() == 0 {
+        return Err(Error::DaVinci("The Markdown file doesn't contain any headings to import as Ideas".to_string()));
+    }
+
+    Ok(roots)
+}
+
+// Finish every open Idea at least as deep as the given depth, adding it to
+// its parent
+fn close_outlines(open_outlines: &mut Vec<(usize, IdeaOutline, Vec<&str>)>, roots: &mut Vec<IdeaOutline>, depth: usize) {
+    while open_outlines.last().map(|open_outline| open_outline.0 >= depth).unwrap_or(false) {
+        let (_, mut outline, mut body) = open_outlines.pop().unwrap();
+
+        // The body is separated from the headings around it by blank lines
+        if body.first() == Some(&"") {
+            body.remove(0);
+        }
+        if body.last() == Some(&"") {
+            body.pop();
+        }
+
+        let fenced = fenced_lines(&body);
+        let description_lines: Vec<String> = body.iter().enumerate().map(|(idx, line)| {
+            if fenced[idx] {
+                line.to_string()
+            } else {
+                unescape(line, is_markup_line)
+            }
+        }).collect();
+        outline.description = description_lines.join("\n");
+
+        match open_outlines.last_mut() {
+            Some(parent) => parent.1.children.push(outline),
+            None => roots.push(outline),
+        }
+    }
+}
+
+fn heading_depth(line: &str) -> Option<usize> {
+    let depth = line.chars().take_while(|&c| c == '#').count();
+    if depth > 0 && (line.len() == depth || line[depth..].starts_with(' ')) {
+        Some(depth)
+    } else {
+        None
+    }
+}
+
+fn parse_heading(heading: &str) -> Result<IdeaOutline> {
+    // Only the space after the #s is markup; the rest belongs to the name
+    let heading = if heading.starts_with(' ') { &heading[1..] } else { heading };
+    let mut words: Vec<&str> = heading.split(' ').collect();
+
+    // Tags are the #words at the end of the heading
+    let mut tags = Vec::new();
+    while words.len() > 1 && is_tag(words[words.len()-1]) {
+        tags.insert(0, words.pop().unwrap()[1..].to_string());
+    }
+
+    let name_words: Vec<String> = words.into_iter().map(|word| unescape(word, is_tag_like)).collect();
+    let mut outline = IdeaOutline::new(name_words.join(" "));
+    outline.tags = tags;
+
+    if outline.name.len() == 0 {
+        return Err(Error::DaVinci(format!("Markdown heading '{}' has no Idea name", heading)));
+    }
+
+    Ok(outline)
+}
+
+fn is_tag(word: &str) -> bool {
+    word.len() > 1 && word.starts_with('#')
+}
+
+fn is_tag_like(word: &str) -> bool {
+    word.trim_left_matches('\\').starts_with('#')
+}
+
+// Lines which would start or end a code block, or be a heading, if it weren't
+// for the backslashes they start with
+fn is_markup_line(line: &str) -> bool {
+    let line = line.trim_left_matches('\\');
+    heading_depth(line).is_some() || fence_marker(line).is_some()
+}
+
+// The character and length of the ``` or ~~~ which starts a code fence line,
+// and whether anything follows it
+fn fence_marker(line: &str) -> Option<(char, usize, bool)> {
+    let indent = line.chars().take(4).take_while(|&c| c == ' ').count();
+    if indent > 3 {
+        return None;
+    }
+
+    let line = &line[indent..];
+    let marker = line.chars().next()?;
+    if marker != '`' && marker != '~' {
+        return None;
+    }
+    let length = line.chars().take_while(|&c| c == marker).count();
+    if length < 3 {
+        return None;
+    }
+    Some((marker, length, line[length..].trim().len() > 0))
+}
+
+// The code fence which is open after a line, and whether the line is part of
+// a code block, fences included
+fn after_line(fence: Option<(char, usize)>, line: &str) -> (Option<(char, usize)>, bool) {
+    match (fence, fence_marker(line)) {
+        (None, Some((marker, length, _))) => (Some((marker, length)), true),
+        (None, None) => (None, false),
+        (Some((marker, length)), Some((line_marker, line_length, false))) if line_marker == marker && line_length >= length => (None, true),
+        (Some(fence), _) => (Some(fence), true),
+    }
+}
+
+// Which lines of a description are in code blocks. If a code block is never
+// closed, none of them count.
+fn fenced_lines(lines: &[&str]) -> Vec<bool> {
+    let mut fence = None;
+    let mut fenced = Vec::new();
+    for line in lines {
+        let (next_fence, in_fence) = after_line(fence, line);
+        fence = next_fence;
+        fenced.push(in_fence);
+    }
+
+    if fence.is_some() {
+        return vec![false; lines.len()];
+    }
+    fenced
+}
+
+// Text which looks like Markdown syntax (even if it's already escaped) gets
+// one more backslash, so unescaping it always restores the original
+fn escape<F>(text: &str, needs_escape: F) -> String
+    where F: Fn(&str) -> bool
+{
+    if needs_escape(text) {
+        format!("\\{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn unescape<F>(text: &str, needs_escape: F) -> String
+    where F: Fn(&str) -> bool
+{
+    if text.starts_with('\\') && needs_escape(text) {
+        text[1..].to_string()
+    } else {
+        text.to_string()
+    }
+}
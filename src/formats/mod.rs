@@ -0,0 +1,43 @@
+pub mod markdown;
+pub mod org;
+pub mod json;
+pub mod opml;
+pub mod html;
+pub mod dot;
+pub mod table;
+pub mod todotxt;
+pub mod ics;
+pub mod directory;
+pub mod text;
+
+// A subtree of Ideas outside of any IdeaTree. Importers parse files into
+// outlines, and exporters which only need names, tags and descriptions write
+// them out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdeaOutline {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub description: String,
+    pub children: Vec<IdeaOutline>,
+}
+
+impl IdeaOutline {
+    pub fn new(name: String) -> IdeaOutline {
+        IdeaOutline {
+            name,
+            tags: Vec::new(),
+            description: String::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+// Split text into lines, without the empty line that would follow a final
+// newline
+pub fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if text.ends_with('\n') {
+        lines.pop();
+    }
+    lines
+}
@@ -0,0 +1,123 @@
+// Ideas are exported to OPML as nested outline elements, which outliners like
+// WorkFlowy, Dynalist and OmniOutliner can open:
+//
+//    <outline text="Books" _note="Description of Books" _tags="reading todo">
+//      <outline text="Dune" _tags="scifi done"/>
+//    </outline>
+//
+// _note is the attribute those outliners use for notes. _tags is Da Vinci
+// Bot's own attribute, holding tags separated by spaces.
+
+use xml::reader::{EventReader, XmlEvent};
+use xml::attribute::OwnedAttribute;
+
+use formats::IdeaOutline;
+use error::{Result, Error};
+
+pub fn to_opml(outline: &IdeaOutline) -> String {
+    let mut opml = String::new();
+
+    opml += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    opml += "<opml version=\"2.0\">\n";
+    opml += "  <head>\n";
+    opml += &format!("    <title>{}</title>\n", escape(&outline.name));
+    opml += "  </head>\n";
+    opml += "  <body>\n";
+    write_outline(&mut opml, outline, 2);
+    opml += "  </body>\n";
+    opml += "</opml>\n";
+
+    opml
+}
+
+fn write_outline(opml: &mut String, outline: &IdeaOutline, depth: usize) {
+    *opml += &"  ".repeat(depth);
+    *opml += &format!("<outline text=\"{}\"", escape(&outline.name));
+    if outline.description.len() > 0 {
+        *opml += &format!(" _note=\"{}\"", escape(&outline.description));
+    }
+    if outline.tags.len() > 0 {
+        *opml += &format!(" _tags=\"{}\"", escape(&outline.tags.join(" ")));
+    }
+
+    if outline.children.len() == 0 {
+        *opml += "/>\n";
+    } else {
+        *opml += ">\n";
+        for child in &outline.children {
+            write_outline(opml, child, depth + 1);
+        }
+        *opml += &"  ".repeat(depth);
+        *opml += "</outline>\n";
+    }
+}
+
+// Escape text for an XML attribute. Whitespace characters are escaped too,
+// because XML parsers turn literal newlines in attributes into spaces.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\n' => escaped += "&#10;",
+            '\r' => escaped += "&#13;",
+            '\t' => escaped += "&#9;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Every outline element in the OPML body becomes an Idea
+pub fn from_opml(opml: &str) -> Result<Vec<IdeaOutline>> {
+    let mut roots = Vec::new();
+    let mut open_outlines: Vec<IdeaOutline> = Vec::new();
+
+    for event in EventReader::from_str(opml) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                if name.local_name == "outline" {
+                    open_outlines.push(outline_from_attributes(attributes)?);
+                }
+            },
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "outline" {
+                    let outline = open_outlines.pop()?;
+                    match open_outlines.last_mut() {
+                        Some(parent) => parent.children.push(outline),
+                        None => roots.push(outline),
+                    }
+                }
+            },
+            _ => { },
+        }
+    }
+
+    if roots.len() == 0 {
+        return Err(Error::DaVinci("The OPML file doesn't contain any outlines to import as Ideas".to_string()));
+    }
+
+    Ok(roots)
+}
+
+fn outline_from_attributes(attributes: Vec<OwnedAttribute>) -> Result<IdeaOutline> {
+    let mut outline = IdeaOutline::new(String::new());
+
+    for attribute in attributes {
+        match attribute.name.local_name.as_str() {
+            "text" => outline.name = attribute.value,
+            "_note" => outline.description = attribute.value,
+            "_tags" => outline.tags = attribute.value.split_whitespace().map(|tag| tag.to_string()).collect(),
+            _ => { },
+        }
+    }
+
+    if outline.name.trim().len() == 0 {
+        return Err(Error::DaVinci("An OPML outline has no text to use as an Idea name".to_string()));
+    }
+
+    Ok(outline)
+}
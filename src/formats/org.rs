@@ -0,0 +1,219 @@
+// Ideas are exported to org-mode as headlines, with the depth of the
+// headline matching the depth of the Idea in the exported subtree:
+//
+//    * TODO Books :reading:todo:
+//      Description of the Books Idea, indented to line up with the headline
+//      text so no line of it can be mistaken for a headline.
+//    ** DONE Dune :sci%2Dfi:done:
+//
+// The done and todo tags are also shown as org's DONE and TODO keywords, but
+// stay in the tag list so their order survives a round trip. A leading TODO
+// or DONE is only read back as a keyword when its tag is in the list, so an
+// Idea named "TODO list" keeps its name.
+//
+// Org only allows letters, digits and _@#% in tags, so any other character is
+// written as % followed by the hex of its UTF-8 bytes, and % itself as %25.
+// The last word of a name is escaped the same way if it looks like tags, like
+// the :urgent: in "Meeting :urgent:", or has a % in it.
+
+use formats::{IdeaOutline, split_lines};
+use error::{Result, Error};
+
+pub fn to_org(outline: &IdeaOutline) -> String {
+    let mut org = String::new();
+    write_outline(&mut org, outline, 1);
+    org
+}
+
+fn write_outline(org: &mut String, outline: &IdeaOutline, depth: usize) {
+    let tags = &outline.tags;
+    let keyword = if tags.contains(&"done".to_string()) {
+        Some("DONE")
+    } else if tags.contains(&"todo".to_string()) {
+        Some("TODO")
+    } else {
+        None
+    };
+
+    *org += &"*".repeat(depth);
+    if let Some(keyword) = keyword {
+        *org += " ";
+        *org += keyword;
+    }
+    *org += " ";
+    *org += &escape_name(&outline.name);
+    if tags.len() > 0 {
+        let tags: Vec<String> = tags.iter().map(|tag| escape_tag(tag)).collect();
+        *org += &format!(" :{}:", tags.join(":"));
+    }
+    *org += "\n";
+
+    if outline.description.len() > 0 {
+        let indent = " ".repeat(depth + 1);
+        for line in outline.description.split('\n') {
+            // Blank lines aren't indented, to avoid trailing whitespace
+            if line.len() > 0 {
+                *org += &indent;
+            }
+            *org += line;
+            *org += "\n";
+        }
+    }
+
+    for child in &outline.children {
+        write_outline(org, child, depth + 1);
+    }
+}
+
+// Every headline in the org file becomes an Idea, nested under the nearest
+// shallower headline above it. Text before the first headline (like #+TITLE)
+// is ignored.
+pub fn from_org(org: &str) -> Result<Vec<IdeaOutline>> {
+    let mut roots = Vec::new();
+
+    // Ideas which can still receive description lines and children, with
+    // their depth and the lines of their body
+    let mut open_outlines: Vec<(usize, IdeaOutline, Vec<&str>)> = Vec::new();
+
+    for line in split_lines(org) {
+        match headline_depth(line) {
+            Some(depth) => {
+                close_outlines(&mut open_outlines, &mut roots, depth);
+                open_outlines.push((depth, parse_headline(&line[depth..])?, Vec::new()));
+            },
+            None => {
+                if let Some(open_outline) = open_outlines.last_mut() {
+                    open_outline.2.push(line);
+                }
+            },
+        }
+    }
+    close_outlines(&mut open_outlines, &mut roots, 0);
+
+    if roots.len() == 0 {
+        return Err(Error::DaVinci("The org file doesn't contain any headlines to import as Ideas".to_string()));
+    }
+
+    Ok(roots)
+}
+
+// Finish every open Idea at least as deep as the given depth, adding it to
+// its parent
+fn close_outlines(open_outlines: &mut Vec<(usize, IdeaOutline, Vec<&str>)>, roots: &mut Vec<IdeaOutline>, depth: usize) {
+    while open_outlines.last().map(|open_outline| open_outline.0 >= depth).unwrap_or(false) {
+        let (outline_depth, mut outline, body) = open_outlines.pop().unwrap();
+
+        // Remove the indentation that lines the body up with the headline
+        let description_lines: Vec<&str> = body.into_iter().map(|line| {
+            let indent = line.chars().take(outline_depth + 1).take_while(|&c| c == ' ').count();
+            &line[indent..]
+        }).collect();
+        outline.description = description_lines.join("\n");
+
+        match open_outlines.last_mut() {
+            Some(parent) => parent.1.children.push(outline),
+            None => roots.push(outline),
+        }
+    }
+}
+
+fn headline_depth(line: &str) -> Option<usize> {
+    let depth = line.chars().take_while(|&c| c == '*').count();
+    if depth > 0 && (line.len() == depth || line[depth..].starts_with(' ')) {
+        Some(depth)
+    } else {
+        None
+    }
+}
+
+fn parse_headline(headline: &str) -> Result<IdeaOutline> {
+    let mut words: Vec<&str> = headline.trim().split(' ').collect();
+
+    // Tags are the :tag1:tag2: at the end of the headline, which org
+    // separates from the headline text with any amount of whitespace
+    let mut tags = Vec::new();
+    if words.len() > 1 {
+        let last_word = words[words.len()-1];
+        if is_tag_word(last_word) {
+            tags = last_word.split(':').filter(|tag| tag.len() > 0).map(unescape_tag).collect();
+            words.pop();
+        }
+    }
+
+    let keyword_tag = match words.first() {
+        Some(&"TODO") => Some("todo"),
+        Some(&"DONE") => Some("done"),
+        _ => None,
+    };
+    if let Some(keyword_tag) = keyword_tag {
+        if words.len() > 1 && tags.contains(&keyword_tag.to_string()) {
+            words.remove(0);
+        }
+    }
+
+    let last_word = words.iter().rposition(|word| word.len() > 0);
+    let name_words: Vec<String> = words.iter().enumerate().map(|(idx, word)| {
+        if Some(idx) == last_word && word.contains('%') {
+            unescape_tag(word)
+        } else {
+            word.to_string()
+        }
+    }).collect();
+    let mut outline = IdeaOutline::new(name_words.join(" ").trim().to_string());
+    outline.tags = tags;
+
+    if outline.name.len() == 0 {
+        return Err(Error::DaVinci(format!("Org headline '{}' has no Idea name", headline)));
+    }
+
+    Ok(outline)
+}
+
+fn is_tag_word(word: &str) -> bool {
+    word.len() > 1 && word.starts_with(':') && word.ends_with(':')
+}
+
+fn escape_name(name: &str) -> String {
+    let words_end = name.trim_right().len();
+    let last_word_start = name[..words_end].rfind(' ').map_or(0, |space| space + 1);
+    let last_word = &name[last_word_start..words_end];
+    if is_tag_word(last_word) || last_word.contains('%') {
+        format!("{}{}{}", &name[..last_word_start], escape_tag(last_word), &name[words_end..])
+    } else {
+        name.to_string()
+    }
+}
+
+fn escape_tag(tag: &str) -> String {
+    let mut escaped = String::new();
+    for c in tag.chars() {
+        if c.is_alphanumeric() || c == '_' || c == '@' || c == '#' {
+            escaped.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                escaped += &format!("%{:02X}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+// A % which isn't followed by two hex digits is kept as it is, for tags
+// written by hand
+fn unescape_tag(tag: &str) -> String {
+    let bytes = tag.as_bytes();
+    let mut unescaped = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len()
+            && (bytes[i+1] as char).is_digit(16) && (bytes[i+2] as char).is_digit(16) {
+            unescaped.push(u8::from_str_radix(&tag[i+1..i+3], 16).unwrap());
+            i += 3;
+            continue;
+        }
+        unescaped.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&unescaped).to_string()
+}
@@ -0,0 +1,226 @@
+// Ideas are exported to CSV or TSV with one row per Idea, for spreadsheets:
+//
+//    id,path,name,tags,description,parent_id,depth
+//    5,Books,Books,reading todo,Things to read.,1,1
+//    6,Books/Dune,Dune,scifi done,,5,2
+//
+// Paths start below the root Idea, whose own path is empty. Slashes and
+// backslashes in names are escaped with a backslash in paths. Tags are
+// separated by spaces.
+//
+// When importing, only the path column is required. Rows are created in
+// order under the Idea being imported into, creating any parents in their
+// paths which don't exist yet. A row with an empty path, like the root's, is
+// the Idea being imported into, and is skipped.
+
+use idea::IdeaTree;
+use error::{Result, Error};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+const COLUMNS: [&str; 7] = ["id", "path", "name", "tags", "description", "parent_id", "depth"];
+
+pub struct TableRow {
+    // The line of the row in a TSV file, or the number of the record in a
+    // CSV file, counting the header as 1
+    pub number: usize,
+    pub path: Vec<String>,
+    pub tags: Vec<String>,
+    pub description: String,
+}
+
+pub fn to_table(tree: &IdeaTree, id: i64, format: TableFormat) -> Result<String> {
+    // Find the path of the exported Idea, so the paths of its descendants
+    // are full paths from the root Idea
+    let mut path = Vec::new();
+    let mut ancestor_id = id;
+    while let Some(parent_id) = tree.get_parent_id(ancestor_id)? {
+        path.insert(0, tree.get_name(ancestor_id)?);
+        ancestor_id = parent_id;
+    }
+
+    let mut table = String::new();
+    write_row(&mut table, &COLUMNS.iter().map(|column| column.to_string()).collect(), format);
+    write_idea(&mut table, tree, id, &mut path, format)?;
+
+    Ok(table)
+}
+
+fn write_idea(table: &mut String, tree: &IdeaTree, id: i64, path: &mut Vec<String>, format: TableFormat) -> Result<()> {
+    let idea = tree.get_idea(id)?;
+
+    let escaped_path: Vec<String> = path.iter().map(|name| escape_path_component(name)).collect();
+    write_row(table, &vec![
+        idea.id.to_string(),
+        escaped_path.join("/"),
+        idea.name.clone(),
+        idea.tags.join(" "),
+        idea.description.clone(),
+        idea.parent_id.map(|parent_id| parent_id.to_string()).unwrap_or(String::new()),
+        path.len().to_string(),
+    ], format);
+
+    for child_id in idea.child_ids {
+        path.push(tree.get_name(child_id)?);
+        write_idea(table, tree, child_id, path, format)?;
+        path.pop();
+    }
+
+    Ok(())
+}
+
+fn write_row(table: &mut String, fields: &Vec<String>, format: TableFormat) {
+    let fields: Vec<String> = fields.iter().map(|field| match format {
+        TableFormat::Csv => quote_csv_field(field),
+        TableFormat::Tsv => escape_tsv_field(field),
+    }).collect();
+
+    *table += &fields.join(match format {
+        TableFormat::Csv => ",",
+        TableFormat::Tsv => "\t",
+    });
+    *table += "\n";
+}
+
+fn quote_csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') || field.trim() != field {
+        format!("\"{}\"", field.replace("\"", "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// TSV fields can't contain tabs or line breaks, so they are escaped the way
+// most TSV tools expect
+fn escape_tsv_field(field: &str) -> String {
+    field.replace("\\", "\\\\").replace("\t", "\\t").replace("\n", "\\n").replace("\r", "\\r")
+}
+
+fn unescape_tsv_field(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn escape_path_component(name: &str) -> String {
+    name.replace("\\", "\\\\").replace("/", "\\/")
+}
+
+fn split_path(path: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut component = String::new();
+
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => if let Some(escaped) = chars.next() {
+                component.push(escaped);
+            },
+            '/' => {
+                components.push(component);
+                component = String::new();
+            },
+            c => component.push(c),
+        }
+    }
+    components.push(component);
+
+    components.into_iter().filter(|component| component.len() > 0).collect()
+}
+
+pub fn from_table(text: &str, format: TableFormat) -> Result<Vec<TableRow>> {
+    // Records are numbered by the line they're on in a TSV file, blank lines
+    // included, and by their position in a CSV file, whose quoted fields can
+    // span lines. Blank records are skipped in both.
+    let mut records: Vec<(usize, Vec<String>)> = match format {
+        TableFormat::Csv => parse_csv(text).into_iter().enumerate()
+            .map(|(index, record)| (index + 1, record))
+            .collect(),
+        TableFormat::Tsv => text.lines().enumerate()
+            .map(|(index, line)| (index + 1, line.split('\t').map(unescape_tsv_field).collect()))
+            .collect(),
+    };
+    records.retain(|&(_, ref record)| record.iter().any(|field| field.len() > 0));
+
+    if records.len() == 0 {
+        return Err(Error::DaVinci("The table is empty".to_string()));
+    }
+
+    let (_, header) = records.remove(0);
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let path_column = match column("path") {
+        Some(path_column) => path_column,
+        None => return Err(Error::DaVinci("The table has no path column".to_string())),
+    };
+    let tags_column = column("tags");
+    let description_column = column("description");
+
+    let field = |record: &Vec<String>, column: Option<usize>| {
+        column.and_then(|column| record.get(column).cloned()).unwrap_or(String::new())
+    };
+
+    Ok(records.into_iter().map(|(number, record)| TableRow {
+        number: number,
+        path: split_path(&field(&record, Some(path_column))),
+        tags: field(&record, tags_column).split_whitespace().map(|tag| tag.to_string()).collect(),
+        description: field(&record, description_column),
+    }).collect())
+}
+
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                },
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_quotes = true,
+            ',' => record.push(field.split_off(0)),
+            '\r' => { },
+            '\n' => {
+                record.push(field.split_off(0));
+                records.push(record.split_off(0));
+            },
+            c => field.push(c),
+        }
+    }
+
+    // The last record may not end with a newline
+    if field.len() > 0 || record.len() > 0 {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
@@ -0,0 +1,167 @@
+// A deterministic, line-oriented text form of every Idea in a tree, which
+// version control can diff and merge line by line:
+//
+//    davincibot text 1
+//
+//    [5]
+//    name: Books
+//    parent: 1
+//    children: 6 7
+//    tag: reading
+//    tag: todo
+//    | Things to read.
+//    |
+//    | Maybe.
+//
+// Ideas are written in order of id. Each description line is prefixed with
+// a bar, so descriptions can contain anything. Backslashes and line breaks
+// in names and tags are escaped.
+
+use idea::Idea;
+use error::{Result, Error};
+
+pub const HEADER: &str = "davincibot text 1";
+
+pub fn to_text(ideas: &[Idea]) -> String {
+    let mut text = String::new();
+    text += HEADER;
+    text += "\n";
+
+    for idea in ideas {
+        text += "\n";
+        text += &idea_to_text(idea);
+    }
+
+    text
+}
+
+pub fn idea_to_text(idea: &Idea) -> String {
+    let mut text = String::new();
+
+    text += &format!("[{}]\n", idea.id);
+    text += &field_line("name", &escape(&idea.name));
+    text += &field_line("parent", &idea.parent_id.map(|id| id.to_string()).unwrap_or("none".to_string()));
+    let child_ids: Vec<String> = idea.child_ids.iter().map(|id| id.to_string()).collect();
+    text += &field_line("children", &child_ids.join(" "));
+    for tag in &idea.tags {
+        text += &field_line("tag", &escape(tag));
+    }
+
+    if idea.description.len() > 0 {
+        for line in idea.description.split('\n') {
+            if line.len() > 0 {
+                text += &format!("| {}\n", line);
+            } else {
+                text += "|\n";
+            }
+        }
+    }
+
+    text
+}
+
+// Lines never end in whitespace, which editors and diff tools might strip
+fn field_line(key: &str, value: &str) -> String {
+    if value.len() > 0 {
+        format!("{}: {}\n", key, value)
+    } else {
+        format!("{}:\n", key)
+    }
+}
+
+fn field_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    if !line.starts_with(key) || !line[key.len()..].starts_with(":") {
+        return None;
+    }
+
+    let value = &line[key.len() + 1..];
+    if value.starts_with(" ") {
+        Some(&value[1..])
+    } else {
+        Some(value)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace("\\", "\\\\").replace("\n", "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+// Parse the text of one or more Ideas. The header is optional, so a single
+// Idea's text can be read on its own.
+pub fn from_text(text: &str) -> Result<Vec<Idea>> {
+    let mut ideas: Vec<Idea> = Vec::new();
+    let mut description_lines: Vec<String> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let error = |message: &str| Error::DaVinci(format!("Line {} of Da Vinci text: {}", idx + 1, message));
+
+        if line == HEADER || line.len() == 0 {
+            continue;
+        }
+
+        if line.starts_with("[") && line.ends_with("]") {
+            if let Some(idea) = ideas.last_mut() {
+                idea.description = description_lines.join("\n");
+            }
+            description_lines.clear();
+
+            ideas.push(Idea {
+                id: line[1..line.len() - 1].parse()?,
+                name: String::new(),
+                description: String::new(),
+                tags: Vec::new(),
+                parent_id: None,
+                child_ids: Vec::new(),
+            });
+            continue;
+        }
+
+        let idea = match ideas.last_mut() {
+            Some(idea) => idea,
+            None => return Err(error("expected an [id] line")),
+        };
+
+        if line == "|" {
+            description_lines.push(String::new());
+        } else if line.starts_with("| ") {
+            description_lines.push(line[2..].to_string());
+        } else if let Some(name) = field_value(line, "name") {
+            idea.name = unescape(name);
+        } else if let Some(parent_id) = field_value(line, "parent") {
+            idea.parent_id = match parent_id {
+                "none" => None,
+                parent_id => Some(parent_id.parse()?),
+            };
+        } else if let Some(child_ids) = field_value(line, "children") {
+            for child_id in child_ids.split_whitespace() {
+                idea.child_ids.push(child_id.parse()?);
+            }
+        } else if let Some(tag) = field_value(line, "tag") {
+            idea.tags.push(unescape(tag));
+        } else {
+            return Err(error("unrecognized line"));
+        }
+    }
+
+    if let Some(idea) = ideas.last_mut() {
+        idea.description = description_lines.join("\n");
+    }
+
+    Ok(ideas)
+}
@@ -0,0 +1,184 @@
+// The leaves of a subtree are exported as a todo.txt task list, one task per
+// line (see https://github.com/todotxt/todo.txt):
+//
+//    x #1 Dune +Books @scifi
+//    Chapter 1 +Books +Emma_#classic due:2018-11-01
+//
+// Done Ideas are marked with an x, the names of their ancestors become
+// +projects, and their other tags become @contexts. Spaces in project and
+// context names are replaced with underscores. Words of a name which would be
+// read as a +project, @context or due: date, or a first word which would be
+// read as an x, (A) priority or date, get a backslash in front of them.
+//
+// Importing a todo.txt file merges it back into the tree: Ideas matching the
+// completed tasks by name (and by project, if more than one has that name)
+// are tagged done.
+//
+// Only files named todo.txt or *.todo.txt are treated as todo.txt files, so
+// other .txt files are never overwritten or mistaken for task lists.
+
+use idea::{IdeaTree, is_date_tag};
+use error::Result;
+
+pub struct TodoTask {
+    pub line: usize,
+    pub done: bool,
+    pub name: String,
+    pub projects: Vec<String>,
+    pub contexts: Vec<String>,
+    pub due: Option<String>,
+}
+
+pub fn is_todo_txt_file(filename: &str) -> bool {
+    let name = filename.rsplit(|c: char| c == '/' || c == '\\').next().unwrap_or(filename).to_lowercase();
+    name == "todo.txt" || name.ends_with(".todo.txt")
+}
+
+pub fn project_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("_")
+}
+
+// The +project names of an Idea's ancestors, not including the root Idea
+pub fn ancestor_projects(tree: &IdeaTree, id: i64) -> Result<Vec<String>> {
+    let mut projects = Vec::new();
+    let mut ancestor_id = tree.get_parent_id(id)?;
+    while let Some(parent_id) = ancestor_id {
+        ancestor_id = tree.get_parent_id(parent_id)?;
+        if ancestor_id.is_some() {
+            projects.insert(0, project_name(&tree.get_name(parent_id)?));
+        }
+    }
+    Ok(projects)
+}
+
+pub fn to_todo_txt(tree: &IdeaTree, id: i64) -> Result<String> {
+    let mut todo_txt = String::new();
+    write_leaves(&mut todo_txt, tree, id)?;
+    Ok(todo_txt)
+}
+
+fn write_leaves(todo_txt: &mut String, tree: &IdeaTree, id: i64) -> Result<()> {
+    let idea = tree.get_idea(id)?;
+
+    let mut is_leaf = true;
+    for child_id in tree.get_child_ids(id, true)? {
+        // Meta Ideas aren't tasks
+        if tree.get_name(child_id)?.starts_with(".") {
+            continue;
+        }
+
+        is_leaf = false;
+        write_leaves(todo_txt, tree, child_id)?;
+    }
+
+    if !is_leaf {
+        return Ok(());
+    }
+
+    let mut line = Vec::new();
+    if idea.tags.contains(&"done".to_string()) {
+        line.push("x".to_string());
+    }
+    line.push(escape_name(&idea.name));
+    for project in ancestor_projects(tree, id)? {
+        line.push(format!("+{}", project));
+    }
+    for tag in &idea.tags {
+        if tag != "todo" && tag != "done" && !is_date_tag(tag) {
+            line.push(format!("@{}", project_name(tag)));
+        }
+    }
+    if let Some(due) = idea.get_date("due") {
+        line.push(format!("due:{}", due));
+    }
+
+    *todo_txt += &line.join(" ");
+    *todo_txt += "\n";
+    Ok(())
+}
+
+fn is_date(word: &str) -> bool {
+    word.len() == 10 && word.chars().enumerate().all(|(idx, c)| match idx {
+        4 | 7 => c == '-',
+        _ => c.is_digit(10),
+    })
+}
+
+fn is_priority(word: &str) -> bool {
+    let chars: Vec<char> = word.chars().collect();
+    chars.len() == 3 && chars[0] == '(' && chars[1].is_ascii_uppercase() && chars[2] == ')'
+}
+
+// Whether a word of a name would be read as something else, even if it's
+// already escaped. Only the first word can be mistaken for an x, a priority
+// or a date.
+fn is_metadata_like(word: &str, first: bool) -> bool {
+    let word = word.trim_left_matches('\\');
+    ((word.starts_with("+") || word.starts_with("@")) && word.len() > 1)
+        || word.starts_with("due:")
+        || (first && (word == "x" || is_priority(word) || is_date(word)))
+}
+
+fn escape_name(name: &str) -> String {
+    let words: Vec<String> = name.split(' ').enumerate().map(|(idx, word)| {
+        if is_metadata_like(word, idx == 0) {
+            format!("\\{}", word)
+        } else {
+            word.to_string()
+        }
+    }).collect();
+    words.join(" ")
+}
+
+pub fn from_todo_txt(text: &str) -> Vec<TodoTask> {
+    let mut tasks = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace().peekable();
+
+        let done = words.peek() == Some(&"x");
+        if done {
+            words.next();
+        }
+
+        // Skip the priority and the completion and creation dates
+        while let Some(&word) = words.peek() {
+            if is_priority(word) || is_date(word) {
+                words.next();
+            } else {
+                break;
+            }
+        }
+
+        let mut task = TodoTask {
+            line: idx + 1,
+            done,
+            name: String::new(),
+            projects: Vec::new(),
+            contexts: Vec::new(),
+            due: None,
+        };
+
+        let mut name_words = Vec::new();
+        for word in words {
+            if word.starts_with("+") && word.len() > 1 {
+                task.projects.push(word[1..].to_string());
+            } else if word.starts_with("@") && word.len() > 1 {
+                task.contexts.push(word[1..].to_string());
+            } else if word.starts_with("due:") {
+                task.due = Some(word["due:".len()..].to_string());
+            } else if word.starts_with("\\") && is_metadata_like(word, name_words.is_empty()) {
+                name_words.push(&word[1..]);
+            } else {
+                name_words.push(word);
+            }
+        }
+        task.name = name_words.join(" ");
+
+        if task.name.len() > 0 {
+            tasks.push(task);
+        }
+    }
+
+    tasks
+}
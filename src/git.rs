@@ -0,0 +1,265 @@
+// Da Vinci trees are often kept in a git repository to sync them between
+// machines. Before a tree is opened, its repository is fetched to warn when
+// somebody else has pushed changes, and a tree with unresolved merge
+// conflicts won't open at all. Changes can be committed with the `commit`
+// command, or automatically at the end of each session with this setting in
+// the root Idea's .settings:
+//
+//    git_commit_on_exit: true
+//
+// Everything runs the git command line tool, so it works with any remote git
+// can reach, including a bare repository on the local disk.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use idea::{Idea, IdeaTree};
+use error::{Result, Error};
+
+// How many changed Ideas to list in the body of an automatic commit message
+const MAX_LISTED_CHANGES: usize = 20;
+
+// Numbers the temporary files old revisions are read from, so reads in
+// different threads don't share one
+static REVISION_FILES: AtomicUsize = AtomicUsize::new(0);
+
+pub struct GitRepo {
+    work_dir: PathBuf,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GitStatus {
+    // Commits on the upstream branch which haven't been pulled
+    pub behind: usize,
+    // Commits which haven't been pushed
+    pub ahead: usize,
+    // Whether the tree file has uncommitted changes
+    pub dirty: bool,
+    // Files with unresolved merge conflicts
+    pub conflicts: Vec<String>,
+}
+
+impl GitRepo {
+    // Find the repository containing a file, if there is one
+    pub fn containing<P: AsRef<Path>>(path: P) -> Option<GitRepo> {
+        let path = path.as_ref();
+        let dir = match path.parent() {
+            Some(dir) if dir.as_os_str().len() > 0 => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if !dir.is_dir() {
+            return None;
+        }
+
+        let repo = GitRepo { work_dir: dir };
+        match repo.git(&["rev-parse", "--show-toplevel"]) {
+            Ok(top_level) => Some(GitRepo { work_dir: PathBuf::from(top_level.trim()) }),
+            Err(_) => None,
+        }
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    // Run git in the repository and return its output, or an error if it fails
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = self.run_git(args)?;
+        if !output.status.success() {
+            return Err(Error::DaVinci(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim())));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn run_git(&self, args: &[&str]) -> Result<Output> {
+        Ok(Command::new("git")
+            .arg("-C").arg(&self.work_dir)
+            .args(args)
+            // Never stop to ask for credentials
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .output()?)
+    }
+
+    // The path of a file relative to the top of the repository, as git names it
+    fn relative_path(&self, path: &Path) -> Result<String> {
+        let path = fs::canonicalize(path)?;
+        let work_dir = fs::canonicalize(&self.work_dir)?;
+        match path.strip_prefix(&work_dir) {
+            Ok(relative_path) => Ok(relative_path.to_string_lossy().replace("\\", "/")),
+            Err(_) => Err(Error::DaVinci(format!("{} is not in the repository at {}", path.display(), work_dir.display()))),
+        }
+    }
+
+    pub fn has_upstream(&self) -> bool {
+        self.git(&["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"]).is_ok()
+    }
+
+    pub fn fetch(&self) -> Result<()> {
+        self.git(&["fetch", "--quiet"])?;
+        Ok(())
+    }
+
+    pub fn status(&self, tree_path: &Path) -> Result<GitStatus> {
+        let mut status = GitStatus::default();
+
+        if self.has_upstream() {
+            let counts = self.git(&["rev-list", "--left-right", "--count", "HEAD...@{u}"])?;
+            let counts: Vec<usize> = counts.split_whitespace().filter_map(|count| count.parse().ok()).collect();
+            if counts.len() == 2 {
+                status.ahead = counts[0];
+                status.behind = counts[1];
+            }
+        }
+
+        let relative_path = self.relative_path(tree_path)?;
+        status.dirty = self.git(&["status", "--porcelain", "--", &relative_path])?.trim().len() > 0;
+        status.conflicts = self.git(&["diff", "--name-only", "--diff-filter=U"])?
+            .lines().map(|line| line.to_string()).collect();
+
+        Ok(status)
+    }
+
+    // Commit the tree file, and its text mirror if it has one in the
+    // repository, if they changed, returning the commit message used
+    pub fn commit(&self, tree: &IdeaTree, message: Option<String>) -> Result<Option<String>> {
+        let tree_path = match tree.path() {
+            Some(path) => path,
+            None => return Err(Error::DaVinci("Can't commit a tree which isn't stored in a file".to_string())),
+        };
+        let relative_path = self.relative_path(tree_path)?;
+
+        let mut paths = vec![relative_path.clone()];
+        if let Some(mirror) = tree.text_mirror() {
+            // A mirror outside the repository isn't part of its history
+            if let Ok(relative_mirror) = self.relative_path(mirror) {
+                if relative_mirror != relative_path {
+                    paths.push(relative_mirror);
+                }
+            }
+        }
+
+        self.git(&with_paths(&["add"], &paths))?;
+        // diff --quiet exits with 1 when there are changes
+        if self.run_git(&with_paths(&["diff", "--cached", "--quiet"], &paths))?.status.success() {
+            return Ok(None);
+        }
+
+        let message = match message {
+            Some(message) => message,
+            None => self.summarize_changes(tree, &relative_path)?,
+        };
+        self.git(&with_paths(&["commit", "--quiet", "-m", &message], &paths))?;
+
+        Ok(Some(message))
+    }
+
+    // The Ideas of the tree file as of a revision, like HEAD~1, or nothing if
+    // the file wasn't in that revision
+    fn read_revision(&self, revision: &str, relative_path: &str) -> Result<Option<Vec<Idea>>> {
+        let committed = self.run_git(&["show", &format!("{}:{}", revision, relative_path)])?;
+        if !committed.status.success() {
+            return Ok(None);
+        }
+
+        let number = REVISION_FILES.fetch_add(1, Ordering::SeqCst);
+        let old_path = env::temp_dir().join(format!("davincibot-revision-{}-{}.dv", process::id(), number));
+        fs::write(&old_path, &committed.stdout)?;
+        let ideas = IdeaTree::open_read_only(&old_path).and_then(|old_tree| old_tree.all_ideas());
+        fs::remove_file(&old_path)?;
+        Ok(Some(ideas?))
+    }
+
+    // Load the tree file as of a revision into memory, to compare it with
+    // the tree
+    pub fn tree_at_revision(&self, tree_path: &Path, revision: &str) -> Result<IdeaTree> {
+        let relative_path = self.relative_path(tree_path)?;
+        match self.read_revision(revision, &relative_path)? {
+            Some(ideas) => IdeaTree::in_memory_from_ideas(&ideas),
+            None => Err(Error::DaVinci(format!("{} isn't in revision {} of the repository at {}", relative_path, revision, self.work_dir.display()))),
+        }
+    }
+
+    // Describe the Ideas that changed since the last commit of the tree file
+    fn summarize_changes(&self, tree: &IdeaTree, relative_path: &str) -> Result<String> {
+        let mut old_ideas = HashMap::new();
+        for idea in self.read_revision("HEAD", relative_path)?.unwrap_or_default() {
+            old_ideas.insert(idea.id, idea);
+        }
+
+        let mut changes = Vec::new();
+        let (mut added, mut changed) = (0, 0);
+        for idea in tree.all_ideas()? {
+            match old_ideas.remove(&idea.id) {
+                None => {
+                    added += 1;
+                    changes.push(format!("Added #{}: {}", idea.id, idea.name));
+                },
+                Some(old_idea) => if is_changed(&old_idea, &idea) {
+                    changed += 1;
+                    changes.push(format!("Changed #{}: {}", idea.id, idea.name));
+                },
+            }
+        }
+
+        let mut removed_ideas: Vec<Idea> = old_ideas.into_iter().map(|(_, idea)| idea).collect();
+        removed_ideas.sort_by_key(|idea| idea.id);
+        for idea in &removed_ideas {
+            changes.push(format!("Removed #{}: {}", idea.id, idea.name));
+        }
+
+        let mut message = format!("Update {}: {} added, {} changed, {} removed", relative_path, added, changed, removed_ideas.len());
+        if changes.len() > 0 {
+            message += "\n\n";
+            let listed = changes.len().min(MAX_LISTED_CHANGES);
+            message += &changes[..listed].join("\n");
+            if changes.len() > listed {
+                message += &format!("\n...and {} more", changes.len() - listed);
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+fn is_changed(old_idea: &Idea, idea: &Idea) -> bool {
+    old_idea.name != idea.name || old_idea.description != idea.description
+        || old_idea.tags != idea.tags || old_idea.parent_id != idea.parent_id
+        || old_idea.child_ids != idea.child_ids
+}
+
+// Check the repository of a tree file before opening it. Returns the status
+// to warn about, or an error if the tree can't be opened safely.
+pub fn check_before_open<P: AsRef<Path>>(tree_path: P) -> Result<Option<GitStatus>> {
+    let tree_path = tree_path.as_ref();
+    let repo = match GitRepo::containing(tree_path) {
+        Some(repo) => repo,
+        None => return Ok(None),
+    };
+
+    // Being offline shouldn't stop anybody from opening their tree
+    let _ = repo.fetch();
+
+    let status = if tree_path.exists() {
+        repo.status(tree_path)?
+    } else {
+        GitStatus::default()
+    };
+    if status.conflicts.len() > 0 {
+        return Err(Error::DaVinci(format!("Refusing to open {} because the repository at {} has unresolved conflicts in: {}",
+            tree_path.display(), repo.work_dir().display(), status.conflicts.join(", "))));
+    }
+
+    Ok(Some(status))
+}
+
+// Git arguments followed by the paths they apply to
+fn with_paths<'a>(args: &[&'a str], paths: &'a [String]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(paths.iter().map(|path| path.as_str()));
+    args
+}
@@ -1,17 +1,25 @@
 extern crate rusqlite;
 extern crate yaml_rust;
 
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use yaml_rust::{YamlLoader, YamlEmitter, Yaml};
 
-use rusqlite::{Connection, Row};
+use rusqlite::{Connection, DatabaseName, OpenFlags, Row};
 use rusqlite::types::{Value, Null, ToSql};
 
 use error::*;
+use formats::{IdeaOutline, markdown, org, json, opml, html, dot, table, todotxt, ics, directory, text};
+use formats::directory::DirImport;
+use formats::table::TableFormat;
+use formats::json::JsonIdea;
+use lock::SessionLock;
 
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
 
 // NOTE unwrap is used below because Da Vinci Bot promises only to put
 // string values in the tags field of the database:
@@ -43,9 +51,79 @@ fn id_vec_to_yaml(vec: Vec<i64>) -> String {
     yaml
 }
 
+// How long a change waits for another session's change to finish
+const BUSY_TIMEOUT_MILLIS: u64 = 5000;
+
+pub const DATE_KEYS: [&str; 2] = ["due", "scheduled"];
+
+// Whether a tag dates its Idea instead of categorizing it
+pub fn is_date_tag(tag: &str) -> bool {
+    DATE_KEYS.iter().any(|key| tag.starts_with(&format!("{}:", key)))
+}
+
+// The current UTC date and time, as (year, month, day, seconds into the day)
+pub fn utc_now() -> (i64, i64, i64, i64) {
+    utc_time(SystemTime::now())
+}
+
+// A UTC date and time, as (year, month, day, seconds into the day)
+pub fn utc_time(time: SystemTime) -> (i64, i64, i64, i64) {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0) as i64;
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, seconds_of_day)
+}
+
+// Today's UTC date in the form date tags use, like 2018-10-30
+pub fn today() -> String {
+    let (year, month, day, _) = utc_now();
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// The modification time, inode and size of a tree's file. Unlike SQLite's
+// data_version, they change when a sync tool replaces the file by renaming
+// another one over it. A directory mirror's time and size are the newest and
+// the total of the directory's files.
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mut modified = metadata.modified().ok()?;
+    let mut size = metadata.len();
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).ok()? {
+            if let Ok(entry_metadata) = entry.and_then(|entry| entry.metadata()) {
+                modified = modified.max(entry_metadata.modified().unwrap_or(modified));
+                size += entry_metadata.len();
+            }
+        }
+    }
+    Some((modified, inode(&metadata), size))
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
 // An Idea is the basic building block of Da Vinci Bot.
 // TODO explain exactly how Ideas work and why
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Idea {
     pub id: i64,
 
@@ -62,6 +140,22 @@ impl Idea {
         Ok(YamlLoader::load_from_str(&self.description)?.into_iter().next())
     }
 
+    // Ideas can be dated with a tag like due:YYYY-MM-DD or scheduled:YYYY-MM-DD,
+    // or with the same keys in their YAML data
+    pub fn get_date(&self, key: &str) -> Option<String> {
+        let prefix = format!("{}:", key);
+        for tag in &self.tags {
+            if tag.starts_with(&prefix) {
+                return Some(tag[prefix.len()..].to_string());
+            }
+        }
+
+        match self.get_yaml_data() {
+            Ok(Some(ref data)) => data[key].as_str().map(|date| date.to_string()),
+            _ => None,
+        }
+    }
+
     pub fn format_name_with_tags(&self) -> String {
         let mut buffer = String::new();
         buffer += &self.name;
@@ -76,29 +170,119 @@ impl Idea {
 
 pub struct IdeaTree {
     conn: Connection,
+    // The file the tree is stored in, unless it's in memory
+    path: Option<PathBuf>,
+    // A text copy of the tree which is rewritten after every change
+    text_mirror: Option<PathBuf>,
+    read_only: bool,
+    // True while a change is being made in a transaction
+    in_transaction: bool,
+    // The Ideas whose text mirror files are written once the transaction in
+    // progress is committed, and whether the whole mirror is
+    mirror_ids: RefCell<Vec<i64>>,
+    mirror_everything: Cell<bool>,
+    // Tells other sessions that this tree is open, once it's been locked
+    lock: Option<SessionLock>,
+    // SQLite's count of changes committed to the file by other connections,
+    // as of the last check
+    data_version: i64,
+    // The file_stamp of the tree's file as of the last check, or the last
+    // change this session made to it
+    file_stamp: Cell<Option<(SystemTime, u64, u64)>>,
+    opened_at: SystemTime,
 }
 
 impl IdeaTree {
     pub fn open_in_memory() -> Result<IdeaTree> { 
-        IdeaTree::create(Connection::open_in_memory()?)
+        IdeaTree::create(Connection::open_in_memory()?, None)
+    }
+
+    // Create a new .dv file holding exactly the given Ideas
+    pub fn create_from_ideas<P: AsRef<Path>>(path: P, ideas: &[Idea]) -> Result<IdeaTree> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            return Err(Error::DaVinci(format!("{} already exists", path.display())));
+        }
+
+        let conn = Connection::open(&path)?;
+        create_table(&conn)?;
+        for idea in ideas {
+            insert_idea_row(&conn, idea)?;
+        }
+
+        IdeaTree::create(conn, Some(path))
+    }
+
+    // Load Ideas, like an old version of a tree, into a tree in memory
+    pub fn in_memory_from_ideas(ideas: &[Idea]) -> Result<IdeaTree> {
+        let conn = Connection::open_in_memory()?;
+        create_table(&conn)?;
+        for idea in ideas {
+            insert_idea_row(&conn, idea)?;
+        }
+
+        IdeaTree::create(conn, None)
     }
 
+    // Open a .dv file, or a text mirror of a tree (a .dvt file or a directory
+    // of .idea files). A text mirror is loaded into memory. Opening a tree
+    // never writes a text mirror; see use_text_mirror.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<IdeaTree> {
-        IdeaTree::create(Connection::open(path)?)
+        IdeaTree::open_with_mode(path.as_ref(), false)
     }
 
-    fn create(conn: Connection) -> Result<IdeaTree> {
-        let mut tree = IdeaTree { conn };
+    // Open a tree which can be read but not changed, like one that another
+    // session is changing
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<IdeaTree> {
+        IdeaTree::open_with_mode(path.as_ref(), true)
+    }
+
+    fn open_with_mode(path: &Path, read_only: bool) -> Result<IdeaTree> {
+        let path = path.to_path_buf();
 
-        // Create the Idea table in the database if one doesn't exist. 
-        tree.conn.execute("CREATE TABLE IF NOT EXISTS ideas (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            description TEXT NOT NULL,
-            tags TEXT NOT NULL,
+        if is_text_mirror_file(&path) || path.is_dir() {
+            let conn = Connection::open_in_memory()?;
+            create_table(&conn)?;
+            for idea in read_text_mirror(&path)? {
+                insert_idea_row(&conn, &idea)?;
+            }
 
-            parent_id INTEGER,
-            child_ids TEXT NOT NULL)", &[])?;
+            let mut tree = IdeaTree::create(conn, Some(path))?;
+            if read_only {
+                tree.conn.execute_batch("PRAGMA query_only = ON")?;
+                tree.read_only = true;
+            }
+            return Ok(tree);
+        }
+
+        if read_only {
+            let mut tree = IdeaTree::create(Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?, Some(path))?;
+            tree.read_only = true;
+            return Ok(tree);
+        }
+
+        IdeaTree::create(Connection::open(&path)?, Some(path))
+    }
+
+    fn create(conn: Connection, path: Option<PathBuf>) -> Result<IdeaTree> {
+        // Wait for other sessions' changes to finish instead of failing
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MILLIS))?;
+        create_table(&conn)?;
+        let mut tree = IdeaTree {
+            conn,
+            path,
+            text_mirror: None,
+            read_only: false,
+            in_transaction: false,
+            mirror_ids: RefCell::new(Vec::new()),
+            mirror_everything: Cell::new(false),
+            lock: None,
+            data_version: 0,
+            file_stamp: Cell::new(None),
+            opened_at: SystemTime::now(),
+        };
+        tree.data_version = tree.read_data_version()?;
+        tree.record_file_stamp();
 
         // Create the root Idea in the database if one doesn't exist.
         if let Err(_) = tree.get_idea(1) {
@@ -164,6 +348,345 @@ impl IdeaTree {
         Ok(())
     }
 
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|path| path.as_path())
+    }
+
+    pub fn text_mirror(&self) -> Option<&Path> {
+        self.text_mirror.as_ref().map(|mirror| mirror.as_path())
+    }
+
+    // Copy the whole tree into a new .dv file with SQLite's online backup,
+    // which is safe to do while other sessions are changing the tree
+    pub fn back_up_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.conn.backup(DatabaseName::Main, path, None)?;
+        Ok(())
+    }
+
+    // When this session opened the tree
+    pub fn opened_at(&self) -> SystemTime {
+        self.opened_at
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // Lock the tree's file, so other sessions know it's open. The lock is
+    // released when the tree is dropped.
+    pub fn lock_session(&mut self) -> Result<()> {
+        if let Some(ref path) = self.path {
+            self.lock = Some(SessionLock::acquire(path)?);
+        }
+        Ok(())
+    }
+
+    fn read_only_error(&self) -> Error {
+        let name = self.path.as_ref().map_or("The tree".to_string(), |path| path.display().to_string());
+        Error::DaVinci(format!("{} is open read-only", name))
+    }
+
+    // Whether an Idea is in a subtree locked by `locked: true` in the
+    // .settings child of the subtree's root. Meta Ideas are never locked, so
+    // a lock can always be lifted by editing the .settings.
+    pub fn is_locked(&self, id: i64) -> Result<bool> {
+        let mut ancestor_id = Some(id);
+        while let Some(id) = ancestor_id {
+            let idea = self.get_idea(id)?;
+            if idea.name.starts_with('.') {
+                return Ok(false);
+            }
+
+            for &child_id in &idea.child_ids {
+                let child = self.get_idea(child_id)?;
+                if child.name == ".settings" {
+                    if let Some(settings) = child.get_yaml_data()? {
+                        if settings["locked"] == Yaml::Boolean(true) {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+            ancestor_id = idea.parent_id;
+        }
+
+        Ok(false)
+    }
+
+    // Refuse to change an Idea in a read-only tree or a locked subtree
+    pub fn check_can_change(&self, id: i64, change: &str) -> Result<()> {
+        if self.read_only {
+            return Err(self.read_only_error());
+        }
+        // Ideas which don't exist are reported by the change itself
+        if self.get_idea(id).is_ok() && self.is_locked(id)? {
+            return Err(Error::DaVinci(format!("Can't {} #{} {} because it's in a locked subtree", change, id, self.get_name(id)?)));
+        }
+        Ok(())
+    }
+
+    // Refuse to change an Idea if it or any of its descendants can't be changed
+    pub fn check_can_change_subtree(&self, id: i64, change: &str) -> Result<()> {
+        let mut ids = vec![id];
+        self.collect_descendant_ids(id, &mut ids)?;
+        for id in ids {
+            self.check_can_change(id, change)?;
+        }
+        Ok(())
+    }
+
+    fn read_data_version(&self) -> Result<i64> {
+        Ok(self.conn.query_row("PRAGMA data_version", &[], |row| row.get(0))?)
+    }
+
+    fn record_file_stamp(&self) {
+        self.file_stamp.set(self.path.as_ref().and_then(|path| file_stamp(path)));
+    }
+
+    // Whether another session or program changed the tree's file since the
+    // last time this was checked. Changes made through this tree don't count.
+    // A tree loaded from a text mirror is reloaded from it, and a .dv file
+    // which was replaced is reopened.
+    pub fn changed_externally(&mut self) -> Result<bool> {
+        let data_version = self.read_data_version()?;
+        let mut changed = data_version != self.data_version;
+        self.data_version = data_version;
+
+        let old_stamp = self.file_stamp.get();
+        self.record_file_stamp();
+        let new_stamp = self.file_stamp.get();
+        if new_stamp != old_stamp {
+            changed = true;
+            if self.is_mirror_backed() {
+                self.reload_text_mirror()?;
+            } else if new_stamp.map(|stamp| stamp.1) != old_stamp.map(|stamp| stamp.1) {
+                self.reopen()?;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    // Replace the Ideas of a tree loaded from a text mirror with the
+    // mirror's current contents
+    fn reload_text_mirror(&mut self) -> Result<()> {
+        let ideas = match self.path {
+            Some(ref path) => read_text_mirror(path)?,
+            None => return Ok(()),
+        };
+
+        self.conn.execute_batch("PRAGMA query_only = OFF; BEGIN; DELETE FROM ideas;")?;
+        for idea in &ideas {
+            if let Err(e) = insert_idea_row(&self.conn, idea) {
+                let _ = self.conn.execute_batch("ROLLBACK");
+                return Err(e);
+            }
+        }
+        self.conn.execute_batch("COMMIT")?;
+        if self.read_only {
+            self.conn.execute_batch("PRAGMA query_only = ON")?;
+        }
+        self.data_version = self.read_data_version()?;
+        Ok(())
+    }
+
+    // Open the tree's .dv file again, after another file replaced it. The
+    // old connection would keep reading the replaced file.
+    fn reopen(&mut self) -> Result<()> {
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if !path.exists() {
+            return Err(Error::DaVinci(format!("{} was deleted outside this session", path.display())));
+        }
+
+        let conn = if self.read_only {
+            Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        } else {
+            Connection::open(&path)?
+        };
+        conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MILLIS))?;
+        create_table(&conn)?;
+        self.conn = conn;
+        self.data_version = self.read_data_version()?;
+        Ok(())
+    }
+
+    // Make a change to the tree in a transaction, so other sessions never see
+    // it half done. BEGIN IMMEDIATE takes the write lock before anything is
+    // read, so the change is based on the latest state of the tree even if
+    // another session changed it. Changes made while one is already in
+    // progress become part of it, in a savepoint, so a failed one is undone
+    // without undoing the rest. The text mirror is only written once the
+    // whole change is committed, so it never shows a change that was undone.
+    fn transaction<T, F>(&mut self, change: F) -> Result<T>
+        where F: FnOnce(&mut IdeaTree) -> Result<T>
+    {
+        if self.read_only {
+            return Err(self.read_only_error());
+        }
+        if self.in_transaction {
+            self.conn.execute_batch("SAVEPOINT nested_change")?;
+            let mirror_ids_len = self.mirror_ids.borrow().len();
+            let mirror_everything = self.mirror_everything.get();
+            let result = change(self);
+            if result.is_err() {
+                let _ = self.conn.execute_batch("ROLLBACK TO nested_change");
+                self.mirror_ids.borrow_mut().truncate(mirror_ids_len);
+                self.mirror_everything.set(mirror_everything);
+            }
+            self.conn.execute_batch("RELEASE nested_change")?;
+            return result;
+        }
+
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        self.in_transaction = true;
+        let result = change(self);
+        self.in_transaction = false;
+        let mirror_ids = self.mirror_ids.replace(Vec::new());
+        let mirror_everything = self.mirror_everything.replace(false);
+
+        match result {
+            Ok(value) => {
+                // A failed COMMIT can leave the transaction open, which would
+                // make later changes part of it
+                if let Err(e) = self.conn.execute_batch("COMMIT") {
+                    let _ = self.conn.execute_batch("ROLLBACK");
+                    return Err(e.into());
+                }
+                if mirror_everything {
+                    self.write_text_mirror_files(None)?;
+                } else if mirror_ids.len() > 0 {
+                    self.write_text_mirror_files(Some(&mirror_ids[..]))?;
+                }
+                self.record_file_stamp();
+                Ok(value)
+            },
+            Err(e) => {
+                // The error which caused the rollback is the one to report
+                let _ = self.conn.execute_batch("ROLLBACK");
+                Err(e)
+            },
+        }
+    }
+
+    // Whether the tree was loaded from a text mirror instead of a .dv file
+    fn is_mirror_backed(&self) -> bool {
+        self.path.as_ref().map_or(false, |path| is_text_mirror_file(path) || path.is_dir())
+    }
+
+    // Write changes to a tree loaded from a text mirror back to it. This
+    // does nothing for a .dv file.
+    pub fn write_back_to_text_mirror(&mut self) {
+        if self.is_mirror_backed() && !self.read_only {
+            self.text_mirror = self.path.clone();
+        }
+    }
+
+    // Turn on the text mirror of the tree a session is editing: the mirror
+    // it was loaded from, or the one the root Idea's text_mirror setting
+    // names, relative to the .dv file. Only the main tree of a session should
+    // do this. Trees opened to diff, merge or restore them share the setting,
+    // and would overwrite the mirror with the wrong contents.
+    pub fn use_text_mirror(&mut self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        if self.is_mirror_backed() {
+            self.write_back_to_text_mirror();
+            return Ok(());
+        }
+
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Yaml::String(mirror) = self.get_setting(1, "text_mirror")? {
+            let mirror_path = path.parent().unwrap_or(Path::new("")).join(mirror);
+            self.mirror_text(mirror_path)?;
+        }
+        Ok(())
+    }
+
+    // Keep a text copy of the tree at the given path: a .dvt file, or a
+    // directory with a file for each Idea named by its id
+    pub fn mirror_text<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.text_mirror = Some(path.as_ref().to_path_buf());
+        self.write_text_mirror(None)
+    }
+
+    // Rewrite the text mirror, if there is one, after the given Ideas changed.
+    // With no ids, the whole mirror is rewritten. A mirror the tree was loaded
+    // from isn't written if something else changed it since, because that
+    // would lose the other change. During a transaction, the Ideas are only
+    // noted, to be written when it's committed.
+    fn write_text_mirror(&self, changed_ids: Option<&[i64]>) -> Result<()> {
+        if let Some(ref path) = self.path {
+            if self.text_mirror.as_ref() == Some(path) && file_stamp(path) != self.file_stamp.get() {
+                return Err(Error::DaVinci(format!("{} was changed outside this session, so this change can't be saved to it. The next command will load the other changes.", path.display())));
+            }
+        }
+
+        if self.in_transaction {
+            match changed_ids {
+                Some(ids) => self.mirror_ids.borrow_mut().extend_from_slice(ids),
+                None => self.mirror_everything.set(true),
+            }
+            return Ok(());
+        }
+
+        self.write_text_mirror_files(changed_ids)?;
+        // The tree's own changes don't count as changes from outside
+        self.record_file_stamp();
+        Ok(())
+    }
+
+    fn write_text_mirror_files(&self, changed_ids: Option<&[i64]>) -> Result<()> {
+        let mirror = match self.text_mirror {
+            Some(ref mirror) => mirror,
+            None => return Ok(()),
+        };
+
+        if is_text_mirror_file(mirror) {
+            fs::write(mirror, text::to_text(&self.all_ideas()?))?;
+            return Ok(());
+        }
+
+        fs::create_dir_all(mirror)?;
+        match changed_ids {
+            Some(ids) => {
+                for &id in ids {
+                    let file = mirror.join(format!("{}.idea", id));
+                    match self.get_idea(id) {
+                        Ok(idea) => fs::write(file, text::idea_to_text(&idea))?,
+                        Err(_) => if file.exists() {
+                            fs::remove_file(file)?;
+                        },
+                    }
+                }
+            },
+            None => {
+                let ideas = self.all_ideas()?;
+                let files: Vec<PathBuf> = ideas.iter().map(|idea| mirror.join(format!("{}.idea", idea.id))).collect();
+
+                // Remove the files of Ideas which no longer exist
+                for entry in fs::read_dir(mirror)? {
+                    let file = entry?.path();
+                    if file.extension().map_or(false, |extension| extension == "idea") && !files.contains(&file) {
+                        fs::remove_file(file)?;
+                    }
+                }
+
+                for (idea, file) in ideas.iter().zip(files) {
+                    fs::write(file, text::idea_to_text(idea))?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
     pub fn error_on_duplicate_child(&self, parent_id: i64, name: String) -> Result<()> {
         // Check for a duplicate-named children.
         let child_ids = self.get_child_ids(parent_id, true)?;
@@ -181,46 +704,51 @@ impl IdeaTree {
     // start with: "exit", "^", "@", or a digit
     // or have leading/trailing whitespace
     pub fn create_idea(&mut self, parent_id: i64, name: String, args: Option<[Option<&ToSql>; 3]>) -> Result<i64> {
-        self.error_on_duplicate_child(parent_id, name.clone())?;
-        if name.trim().len() == 0 {
-            return Err(Error::DaVinci("Can't make an Idea without a name".to_string()));
-        }
+        self.transaction(|tree| {
+            if !name.starts_with('.') {
+                tree.check_can_change(parent_id, "add Ideas to")?;
+            }
+            tree.error_on_duplicate_child(parent_id, name.clone())?;
+            if name.trim().len() == 0 {
+                return Err(Error::DaVinci("Can't make an Idea without a name".to_string()));
+            }
 
-        let new_id = {
+            let new_id = {
 
-            let mut statement = self.conn.prepare_cached("INSERT INTO ideas (name, description, tags, child_ids, parent_id) VALUES (?, ?, ?, ?, ?)")?; 
-            let default_args: [&ToSql; 5] = [
-                &name, // Name
-                &"", // Description
-                &"[]", // Tags
-                &"[]", // Child IDS
-                &Null, // Parent ID
-            ];
+                let mut statement = tree.conn.prepare_cached("INSERT INTO ideas (name, description, tags, child_ids, parent_id) VALUES (?, ?, ?, ?, ?)")?; 
+                let default_args: [&ToSql; 5] = [
+                    &name, // Name
+                    &"", // Description
+                    &"[]", // Tags
+                    &"[]", // Child IDS
+                    &Null, // Parent ID
+                ];
 
-            // TODO document how to create an Idea with preset field values
-            // by passing a ToSql array.
-            let mut creation_args: Vec<&ToSql> = Vec::new();
+                // TODO document how to create an Idea with preset field values
+                // by passing a ToSql array.
+                let mut creation_args: Vec<&ToSql> = Vec::new();
             
-            if let Some(user_args) = args {
-                creation_args.push(&name);
-                creation_args.extend(
-                    user_args.into_iter().enumerate().map(|arg| match arg {
-                        (_, Some(arg)) => arg.clone(),
-                        (idx, None) => default_args[idx+1].clone(),
-                    }));
-                creation_args.push(&Null);
-            }
-            else {
-                creation_args.extend(default_args.iter());
-            }
+                if let Some(user_args) = args {
+                    creation_args.push(&name);
+                    creation_args.extend(
+                        user_args.into_iter().enumerate().map(|arg| match arg {
+                            (_, Some(arg)) => arg.clone(),
+                            (idx, None) => default_args[idx+1].clone(),
+                        }));
+                    creation_args.push(&Null);
+                }
+                else {
+                    creation_args.extend(default_args.iter());
+                }
 
 
-            statement.execute(&creation_args)?;
-            self.conn.last_insert_rowid()
-        };
+                statement.execute(&creation_args)?;
+                tree.conn.last_insert_rowid()
+            };
 
-        self.set_parent(new_id, parent_id)?;
-        Ok(new_id)
+            tree.set_parent(new_id, parent_id)?;
+            Ok(new_id)
+        })
     }
 
 
@@ -271,22 +799,93 @@ impl IdeaTree {
     }
 
     pub fn set_parent(&mut self, child_id: i64, parent_id: i64) -> Result<()> {
-        if child_id == 1 {
-            return Err(Error::DaVinci("Cannot move the Root idea.".to_string()));
-        }
+        self.transaction(|tree| {
+            if child_id == 1 {
+                return Err(Error::DaVinci("Cannot move the Root idea.".to_string()));
+            }
+            // Moving a subtree changes every Idea in it, and the Ideas it's
+            // moved into. Meta Ideas are never locked, wherever they go.
+            tree.check_can_change_subtree(child_id, "move")?;
+            if !tree.get_name(child_id)?.starts_with('.') {
+                tree.check_can_change(parent_id, "move Ideas into")?;
+            }
+            if child_id == parent_id || tree.is_ancestor(child_id, parent_id)? {
+                return Err(Error::DaVinci(format!("Can't move #{} {} into its own subtree", child_id, tree.get_name(child_id)?)));
+            }
 
-        // Get the child's old parent so we can sever that bond later
-        let old_parent_id = self.get_parent_id(child_id)?;
+            // Get the child's old parent so we can sever that bond later
+            let old_parent_id = tree.get_parent_id(child_id)?;
 
-        // Attempt to add the child to the new parent FIRST, because this may
-        // fail if its name is a duplicate
-        self.add_child(parent_id, child_id)?;
+            // Attempt to add the child to the new parent FIRST, because this may
+            // fail if its name is a duplicate
+            tree.add_child(parent_id, child_id)?;
 
-        if let Some(old_parent_id) = old_parent_id {
-            // Finally, sever the old parent-child relationship
-            self.remove_child(old_parent_id, child_id)?;
-        }
-        Ok(())
+            if let Some(old_parent_id) = old_parent_id {
+                // Finally, sever the old parent-child relationship
+                tree.remove_child(old_parent_id, child_id)?;
+            }
+
+            let mut changed_ids = vec![child_id, parent_id];
+            changed_ids.extend(old_parent_id);
+            tree.write_text_mirror(Some(&changed_ids[..]))
+        })
+    }
+
+    // Copy an Idea and all of its descendants, with their tags and
+    // descriptions, under a parent, returning the id of the copy. The copy
+    // keeps the original's name unless it's given a new one.
+    pub fn copy_subtree(&mut self, src: i64, dest_parent: i64, new_name: Option<String>) -> Result<i64> {
+        self.transaction(|tree| {
+            let mut outline = tree.get_outline(src)?;
+            if let Some(new_name) = new_name {
+                outline.name = new_name;
+            }
+
+            Ok(tree.import_outlines(dest_parent, vec![outline])?[0])
+        })
+    }
+
+    // Create a new Idea named `name` under a parent by copying a child of the
+    // nearest .templates Idea. {{name}} and {{date}} in the names, tags and
+    // descriptions of the copy are replaced with the new name and today's date.
+    pub fn instantiate_template(&mut self, parent_id: i64, template: &str, name: String) -> Result<i64> {
+        self.transaction(|tree| {
+            let templates = match tree.get_meta_idea(parent_id, "templates")? {
+                Some(templates) => templates,
+                None => return Err(Error::DaVinci("There is no .templates Idea to make new Ideas from".to_string())),
+            };
+            let template = tree.get_child_by_name_hint(templates.id, template.to_string())?;
+
+            let mut outline = tree.get_outline(template.id)?;
+            fill_placeholders(&mut outline, &[("name", &name), ("date", &today())]);
+            outline.name = name;
+
+            Ok(tree.import_outlines(parent_id, vec![outline])?[0])
+        })
+    }
+
+    // Delete an Idea and all of its descendants
+    pub fn delete_subtree(&mut self, id: i64) -> Result<()> {
+        self.transaction(|tree| {
+            if id == 1 {
+                return Err(Error::DaVinci("Cannot delete the Root idea.".to_string()));
+            }
+            tree.check_can_change_subtree(id, "delete")?;
+
+            let parent_id = tree.get_parent_id(id)?;
+            if let Some(parent_id) = parent_id {
+                tree.remove_child(parent_id, id)?;
+            }
+
+            let mut deleted_ids = vec![id];
+            tree.collect_descendant_ids(id, &mut deleted_ids)?;
+            for &deleted_id in &deleted_ids {
+                tree.conn.execute("DELETE FROM ideas WHERE id=?", &[&deleted_id])?;
+            }
+
+            deleted_ids.extend(parent_id);
+            tree.write_text_mirror(Some(&deleted_ids[..]))
+        })
     }
 
     pub fn get_name(&self, id: i64) -> Result<String> {
@@ -306,11 +905,26 @@ impl IdeaTree {
         Ok(buffer)
     }
 
+    // The names of an Idea's ancestors below the root, and its own, joined
+    // by slashes
+    pub fn get_path(&self, id: i64) -> Result<String> {
+        let mut names = Vec::new();
+        let mut ancestor_id = id;
+        while let Some(parent_id) = self.get_parent_id(ancestor_id)? {
+            names.insert(0, self.get_name(ancestor_id)?);
+            ancestor_id = parent_id;
+        }
+        Ok(names.join("/"))
+    }
+
     pub fn set_name(&self, id: i64, name: &String) -> Result<()> {
+        self.check_can_change(id, "rename")?;
         let mut statement = self.conn.prepare_cached("UPDATE ideas SET name=? WHERE id=?")?;
 
-        statement.execute(&[name, &id])?;
-        Ok(())
+        if statement.execute(&[name, &id])? == 0 {
+            return Err(missing_idea(id));
+        }
+        self.write_text_mirror(Some(&[id][..]))
     }
 
     pub fn get_description(&self, id: i64) -> Result<String> {
@@ -319,10 +933,13 @@ impl IdeaTree {
     }
 
     pub fn set_description(&mut self, id: i64, description: &String) -> Result<()> {
+        self.check_can_change(id, "describe")?;
         let mut statement = self.conn.prepare_cached("UPDATE ideas SET description=? WHERE id=?")?;
 
-        statement.execute(&[description, &id])?;
-        Ok(())
+        if statement.execute(&[description, &id])? == 0 {
+            return Err(missing_idea(id));
+        }
+        self.write_text_mirror(Some(&[id][..]))
     }
 
     pub fn get_tags(&self, id: i64, inherit_tags: bool) -> Result<Vec<String>> {
@@ -343,10 +960,13 @@ impl IdeaTree {
     }
 
     pub fn set_tags(&mut self, id: i64, tags: Vec<String>) -> Result<()> {
+        self.check_can_change(id, "change the tags of")?;
         let mut statement = self.conn.prepare_cached("UPDATE ideas SET tags=? WHERE id=?")?;
 
-        statement.execute(&[&tag_vec_to_yaml(tags), &id])?;
-        Ok(())
+        if statement.execute(&[&tag_vec_to_yaml(tags), &id])? == 0 {
+            return Err(missing_idea(id));
+        }
+        self.write_text_mirror(Some(&[id][..]))
     }
 
     pub fn clear_tags(&mut self, id: i64) -> Result<()> {
@@ -354,16 +974,20 @@ impl IdeaTree {
     }
 
     pub fn remove_tags(&mut self, id: i64, tags: Vec<String>) -> Result<()> {
-        let mut new_tags = self.get_tags(id, false)?;
-        new_tags.retain(|tag| !tags.contains(tag));
-        self.set_tags(id, new_tags)
+        self.transaction(|tree| {
+            let mut new_tags = tree.get_tags(id, false)?;
+            new_tags.retain(|tag| !tags.contains(tag));
+            tree.set_tags(id, new_tags)
+        })
     }
 
     pub fn add_tags(&mut self, id: i64, tags: Vec<String>) -> Result<()> {
-        let mut new_tags = self.get_tags(id, false)?;
-        new_tags.extend(tags);
+        self.transaction(|tree| {
+            let mut new_tags = tree.get_tags(id, false)?;
+            new_tags.extend(tags);
 
-        self.set_tags(id, new_tags)
+            tree.set_tags(id, new_tags)
+        })
     }
 
     pub fn get_parent_id(&self, id: i64) -> Result<Option<i64>> {
@@ -375,7 +999,13 @@ impl IdeaTree {
         }
     }
 
-    /*pub fn get_meta_yaml(&self, id: i64, */
+    pub fn get_meta_yaml(&self, id: i64, meta_type: &str) -> Result<Option<Yaml>> {
+        match self.get_meta_idea(id, meta_type)? {
+            Some(meta_idea) => meta_idea.get_yaml_data(),
+            // It's not an error if there is no meta idea with that name
+            None => Ok(None),
+        }
+    }
 
     pub fn get_meta_idea(&self, id: i64, meta_type: &str) -> Result<Option<Idea>> {
         let idea = self.get_idea(id)?;
@@ -396,6 +1026,15 @@ impl IdeaTree {
         }
     }
 
+    // Get one setting from the nearest .settings Idea, or BadValue if it
+    // isn't set there
+    pub fn get_setting(&self, id: i64, setting: &str) -> Result<Yaml> {
+        match self.get_meta_yaml(id, "settings")? {
+            Some(settings_yaml) => Ok(settings_yaml[setting].clone()),
+            None => Ok(Yaml::BadValue),
+        }
+    }
+
     pub fn get_meta_tags(&self, id: i64, meta_type: &str) -> Result<Vec<String>> {
         let idea = self.get_meta_idea(id, meta_type)?;
 
@@ -440,6 +1079,18 @@ impl IdeaTree {
         Ok(idea)
     }
 
+    // Every Idea in the tree, in order of id
+    pub fn all_ideas(&self) -> Result<Vec<Idea>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM ideas ORDER BY id")?;
+        let rows = stmt.query_map(&[], |row| idea_from_row(row))?;
+
+        let mut ideas = Vec::new();
+        for idea in rows {
+            ideas.push(idea?);
+        }
+        Ok(ideas)
+    }
+
     pub fn search_ideas(&self, hint: &String) -> Result<Vec<Idea>> {
         let full_pattern = format!("%{}%", hint);
         let mut stmt = self.conn.prepare("SELECT * FROM ideas WHERE name||tags||description LIKE ?")?;
@@ -470,21 +1121,391 @@ impl IdeaTree {
         }
     }
 
-    pub fn export_idea(&self, id: i64, filename: &String) -> Result<()> {
-        let extension = {
-            let dot_index = filename.rfind('.')?;
-            &filename[dot_index..]
+    // Find the child with exactly the given name, if there is one
+    pub fn find_child(&self, parent_id: i64, name: &str) -> Result<Option<i64>> {
+        for child_id in self.get_child_ids(parent_id, true)? {
+            if self.get_name(child_id)? == name {
+                return Ok(Some(child_id));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Read an Idea and all of its descendants, including hidden ones
+    pub fn get_outline(&self, id: i64) -> Result<IdeaOutline> {
+        let idea = self.get_idea(id)?;
+
+        let mut children = Vec::new();
+        for child_id in idea.child_ids {
+            children.push(self.get_outline(child_id)?);
+        }
+
+        Ok(IdeaOutline {
+            name: idea.name,
+            tags: idea.tags,
+            description: idea.description,
+            children,
+        })
+    }
+
+    // Create new Ideas from outlines under the given parent, returning the ids
+    // of the top-level Ideas created
+    pub fn import_outlines(&mut self, parent_id: i64, outlines: Vec<IdeaOutline>) -> Result<Vec<i64>> {
+        self.transaction(|tree| {
+            let mut new_ids = Vec::new();
+
+            for outline in outlines {
+                let tags_yaml = tag_vec_to_yaml(outline.tags);
+                let new_id = tree.create_idea(parent_id, outline.name, Some([
+                    Some(&outline.description),
+                    Some(&tags_yaml),
+                    None,
+                ]))?;
+
+                tree.import_outlines(new_id, outline.children)?;
+                new_ids.push(new_id);
+            }
+
+            Ok(new_ids)
+        })
+    }
+
+    pub fn import_idea(&mut self, parent_id: i64, filename: &String) -> Result<Vec<i64>> {
+        let contents = read_file(filename)?;
+
+        let outlines = match file_extension(filename)? {
+            ".md" => markdown::from_markdown(&contents)?,
+            ".org" => org::from_org(&contents)?,
+            ".json" => vec![json::from_json(&contents)?.into_outline()],
+            ".opml" => opml::from_opml(&contents)?,
+            extension => {
+                return Err(Error::DaVinci(format!("Requested import from unsupported format '{}'", extension)));
+            }
+        };
+
+        self.import_outlines(parent_id, outlines)
+    }
+
+    // Create Ideas from the rows of a CSV or TSV file under the given parent.
+    // A row which can't be imported doesn't stop the rest of the import, and
+    // its error is returned with its row number instead.
+    pub fn import_table(&mut self, parent_id: i64, filename: &String) -> Result<(Vec<i64>, Vec<(usize, Error)>)> {
+        let format = match file_extension(filename)? {
+            ".csv" => TableFormat::Csv,
+            ".tsv" => TableFormat::Tsv,
+            extension => {
+                return Err(Error::DaVinci(format!("Requested table import from unsupported format '{}'", extension)));
+            }
+        };
+
+        let rows = table::from_table(&read_file(filename)?, format)?;
+        self.transaction(|tree| {
+            let mut new_ids = Vec::new();
+            let mut row_errors = Vec::new();
+            for table::TableRow { number, path, tags, description } in rows {
+                // The row of the Idea being imported into, like the root's row
+                // in an export of the whole tree
+                if path.is_empty() {
+                    continue;
+                }
+                // Each row gets its own savepoint, so a row which fails
+                // doesn't leave behind the parents it created
+                match tree.transaction(|tree| tree.import_row(parent_id, path, tags, description)) {
+                    Ok(new_id) => new_ids.push(new_id),
+                    Err(e) => row_errors.push((number, e)),
+                }
+            }
+
+            Ok((new_ids, row_errors))
+        })
+    }
+
+    fn import_row(&mut self, parent_id: i64, mut path: Vec<String>, tags: Vec<String>, description: String) -> Result<i64> {
+        let name = match path.pop() {
+            Some(name) => name,
+            None => return Err(Error::DaVinci("The row has no path".to_string())),
+        };
+
+        // Intermediate parents are reused if they exist, and created if not
+        let mut row_parent_id = parent_id;
+        for parent_name in path {
+            row_parent_id = match self.find_child(row_parent_id, &parent_name)? {
+                Some(id) => id,
+                None => self.create_idea(row_parent_id, parent_name, None)?,
+            };
+        }
+
+        let tags_yaml = tag_vec_to_yaml(tags);
+        self.create_idea(row_parent_id, name, Some([
+            Some(&description),
+            Some(&tags_yaml),
+            None,
+        ]))
+    }
+
+    // Tag done the Ideas under the given parent which match completed tasks in
+    // a todo.txt file. Tasks which don't match exactly one Idea are returned
+    // as errors with their line numbers.
+    pub fn merge_todo_txt(&mut self, parent_id: i64, filename: &String) -> Result<(Vec<i64>, Vec<(usize, Error)>)> {
+        let tasks = todotxt::from_todo_txt(&read_file(filename)?);
+        self.transaction(|tree| tree.merge_todo_tasks(parent_id, tasks))
+    }
+
+    fn merge_todo_tasks(&mut self, parent_id: i64, tasks: Vec<todotxt::TodoTask>) -> Result<(Vec<i64>, Vec<(usize, Error)>)> {
+        let mut descendant_ids = Vec::new();
+        self.collect_descendant_ids(parent_id, &mut descendant_ids)?;
+
+        let mut done_ids = Vec::new();
+        let mut line_errors = Vec::new();
+        for task in tasks {
+            if !task.done {
+                continue;
+            }
+
+            let mut matching_ids = Vec::new();
+            for &id in &descendant_ids {
+                if self.get_name(id)? != task.name {
+                    continue;
+                }
+                let projects = todotxt::ancestor_projects(self, id)?;
+                if task.projects.iter().all(|project| projects.contains(project)) {
+                    matching_ids.push(id);
+                }
+            }
+
+            match matching_ids.len() {
+                0 => line_errors.push((task.line, Error::DaVinci(format!("No Idea matches completed task '{}'", task.name)))),
+                1 => {
+                    let id = matching_ids[0];
+                    if !self.get_tags(id, false)?.contains(&"done".to_string()) {
+                        self.add_tags(id, vec!["done".to_string()])?;
+                    }
+                    done_ids.push(id);
+                },
+                _ => line_errors.push((task.line, Error::DaVinci(format!("Multiple Ideas match completed task '{}'", task.name)))),
+            }
+        }
+
+        Ok((done_ids, line_errors))
+    }
+
+    fn is_ancestor(&self, ancestor_id: i64, id: i64) -> Result<bool> {
+        let mut parent_id = self.get_parent_id(id)?;
+        while let Some(id) = parent_id {
+            if id == ancestor_id {
+                return Ok(true);
+            }
+            parent_id = self.get_parent_id(id)?;
+        }
+        Ok(false)
+    }
+
+    // Add the ids of all of an Idea's descendants, meta Ideas included
+    pub fn collect_descendant_ids(&self, id: i64, descendant_ids: &mut Vec<i64>) -> Result<()> {
+        for child_id in self.get_child_ids(id, true)? {
+            descendant_ids.push(child_id);
+            self.collect_descendant_ids(child_id, descendant_ids)?;
+        }
+        Ok(())
+    }
+
+    // Import a directory from disk under the given parent, or sync it if it was
+    // imported there before
+    pub fn import_dir(&mut self, parent_id: i64, options: DirImport) -> Result<i64> {
+        self.transaction(|tree| directory::import_dir(tree, parent_id, options))
+    }
+
+    // Sync an Idea imported from a directory with the directory's contents
+    pub fn sync_dir(&mut self, dir_id: i64) -> Result<i64> {
+        self.transaction(|tree| directory::sync_dir(tree, dir_id))
+    }
+
+    // Import a JSON export under the given parent, keeping the ids it was
+    // exported with. Ideas whose ids are already in the tree are updated and
+    // moved instead of duplicated, unless the id belongs to a different Idea,
+    // in which case the imported Idea gets a new id. Ideas which would be
+    // moved into their own subtrees stay where they are, and are returned as
+    // errors with their ids.
+    pub fn merge_json(&mut self, parent_id: i64, filename: &String) -> Result<(Vec<i64>, Vec<(i64, Error)>)> {
+        let json_idea = json::from_json(&read_file(filename)?)?;
+
+        self.transaction(|tree| {
+            let mut parent_ids = HashMap::new();
+            for idea in tree.all_ideas()? {
+                parent_ids.insert(idea.id, idea.parent_id);
+            }
+            let mut stuck_ids = Vec::new();
+            tree.plan_json_moves(&mut parent_ids, &mut -1, &mut stuck_ids, parent_id, &json_idea)?;
+
+            let new_id = tree.merge_json_idea(parent_id, json_idea, &stuck_ids)?;
+            let mut errors = Vec::new();
+            for id in stuck_ids {
+                let message = format!("#{} {} wasn't moved, because it would be moved into its own subtree", id, tree.get_name(id)?);
+                errors.push((id, Error::DaVinci(message)));
+            }
+            Ok((vec![new_id], errors))
+        })
+    }
+
+    // Work out where merging a JSON Idea would move each Idea, before
+    // anything changes, to find the Ideas which would end up in their own
+    // subtrees. Ideas which will be created get made-up negative ids.
+    fn plan_json_moves(&self, parent_ids: &mut HashMap<i64, Option<i64>>, next_new_id: &mut i64, stuck_ids: &mut Vec<i64>, parent_id: i64, json_idea: &JsonIdea) -> Result<()> {
+        let id = match json_idea.id {
+            Some(id) if self.is_same_idea(id, parent_id, &json_idea.name)? => {
+                if id != 1 && parent_ids.get(&id) != Some(&Some(parent_id)) {
+                    // Following the parents up from the new parent would
+                    // reach the Idea itself
+                    let mut ancestor_id = Some(parent_id);
+                    while let Some(ancestor) = ancestor_id {
+                        if ancestor == id {
+                            break;
+                        }
+                        ancestor_id = parent_ids.get(&ancestor).cloned().unwrap_or(None);
+                    }
+                    if ancestor_id.is_some() {
+                        stuck_ids.push(id);
+                    } else {
+                        parent_ids.insert(id, Some(parent_id));
+                    }
+                }
+                id
+            },
+            Some(id) if self.get_idea(id).is_err() => {
+                parent_ids.insert(id, Some(parent_id));
+                id
+            },
+            _ => {
+                let new_id = *next_new_id;
+                *next_new_id -= 1;
+                parent_ids.insert(new_id, Some(parent_id));
+                new_id
+            },
+        };
+
+        for child in &json_idea.children {
+            self.plan_json_moves(parent_ids, next_new_id, stuck_ids, id, child)?;
+        }
+        Ok(())
+    }
+
+    // Whether the Idea with an id is the one a JSON Idea with the same id was
+    // exported from. SQLite reuses the ids of deleted Ideas, so an unrelated
+    // Idea can have the id. The same Idea may have been renamed or moved
+    // since, but not both, and never into or out of being a meta Idea.
+    fn is_same_idea(&self, id: i64, parent_id: i64, name: &str) -> Result<bool> {
+        let existing = match self.get_idea(id) {
+            Ok(existing) => existing,
+            Err(_) => return Ok(false),
         };
 
+        Ok(existing.name.starts_with('.') == name.starts_with('.')
+            && (existing.name == name || existing.parent_id == Some(parent_id)))
+    }
+
+    fn merge_json_idea(&mut self, parent_id: i64, json_idea: JsonIdea, stuck_ids: &[i64]) -> Result<i64> {
+        let tags_yaml = tag_vec_to_yaml(json_idea.tags.clone());
+
+        let id = match json_idea.id {
+            Some(id) if self.is_same_idea(id, parent_id, &json_idea.name)? => {
+                if self.get_name(id)? != json_idea.name {
+                    self.error_on_duplicate_child(parent_id, json_idea.name.clone())?;
+                }
+                self.set_name(id, &json_idea.name)?;
+                self.set_tags(id, json_idea.tags)?;
+                self.set_description(id, &json_idea.description)?;
+
+                // The root Idea can be updated, but not moved
+                if id != 1 && !stuck_ids.contains(&id) && self.get_parent_id(id)? != Some(parent_id) {
+                    self.set_parent(id, parent_id)?;
+                }
+                id
+            },
+            Some(id) if self.get_idea(id).is_err() => {
+                if !json_idea.name.starts_with('.') {
+                    self.check_can_change(parent_id, "add Ideas to")?;
+                }
+                self.error_on_duplicate_child(parent_id, json_idea.name.clone())?;
+
+                {
+                    let mut statement = self.conn.prepare_cached("INSERT INTO ideas (id, name, description, tags, child_ids, parent_id) VALUES (?, ?, ?, ?, ?, ?)")?;
+                    let args: &[&ToSql] = &[
+                        &id,
+                        &json_idea.name,
+                        &json_idea.description,
+                        &tags_yaml,
+                        &"[]", // Child IDS
+                        &Null, // Parent ID
+                    ];
+                    statement.execute(args)?;
+                }
+
+                self.set_parent(id, parent_id)?;
+                id
+            },
+            _ => {
+                self.create_idea(parent_id, json_idea.name, Some([
+                    Some(&json_idea.description),
+                    Some(&tags_yaml),
+                    None,
+                ]))?
+            },
+        };
+
+        for child in json_idea.children {
+            self.merge_json_idea(id, child, stuck_ids)?;
+        }
+
+        Ok(id)
+    }
+
+    // Export an Idea and its descendants to a file, in the format its
+    // extension names. Ideas which had to be left out are returned as errors.
+    pub fn export_idea(&self, id: i64, filename: &String) -> Result<Vec<Error>> {
+        let extension = file_extension(filename)?;
+        // Other text files are left alone instead of being overwritten
+        if extension == ".txt" && !todotxt::is_todo_txt_file(filename) {
+            return Err(Error::DaVinci(format!("Can't export to {}: only files named todo.txt or *.todo.txt are exported as todo.txt", filename)));
+        }
+
+        let mut skipped = Vec::new();
         {
             let mut file = OpenOptions::new().write(true).
                 create(true).truncate(true).open(filename)?;
 
-            let selected_idea = self.get_idea(id)?;
-
             match extension {
                 ".org" => {
-                    file.write(idea_to_org_string(selected_idea, self, 1)?.as_bytes());
+                    file.write_all(org::to_org(&self.get_outline(id)?).as_bytes())?;
+                }
+                ".md" => {
+                    file.write_all(markdown::to_markdown(&self.get_outline(id)?).as_bytes())?;
+                }
+                ".json" => {
+                    file.write_all(json::to_json(self, id)?.as_bytes())?;
+                }
+                ".opml" => {
+                    file.write_all(opml::to_opml(&self.get_outline(id)?).as_bytes())?;
+                }
+                ".html" => {
+                    file.write_all(html::to_html(self, id)?.as_bytes())?;
+                }
+                ".dot" => {
+                    file.write_all(dot::to_dot(self, id)?.as_bytes())?;
+                }
+                ".csv" => {
+                    file.write_all(table::to_table(self, id, TableFormat::Csv)?.as_bytes())?;
+                }
+                ".tsv" => {
+                    file.write_all(table::to_table(self, id, TableFormat::Tsv)?.as_bytes())?;
+                }
+                ".ics" => {
+                    let (ics, ics_skipped) = ics::to_ics(self, id)?;
+                    file.write_all(ics.as_bytes())?;
+                    skipped = ics_skipped;
+                }
+                ".txt" => {
+                    file.write_all(todotxt::to_todo_txt(self, id)?.as_bytes())?;
                 }
                 _ => {
                     return Err(Error::DaVinci(format!("Requested export to unsupported format '{}'", extension)));
@@ -492,42 +1513,92 @@ impl IdeaTree {
             }
         }
 
-        Ok(())
+        Ok(skipped)
     }
 
 }
 
-fn idea_to_org_string(idea: Idea, tree: &IdeaTree, depth: usize) -> Result<String> {
-    let mut result = "".to_string();
-    for i in 0..depth {
-        result += "*";
-    }
-    result += " ";
+fn missing_idea(id: i64) -> Error {
+    Error::DaVinci(format!("Idea #{} doesn't exist. It may have been deleted in another session.", id))
+}
+
+fn fill_placeholders(outline: &mut IdeaOutline, values: &[(&str, &str)]) {
+    let fill = |text: &str| values.iter().fold(text.to_string(), |text, &(key, value)| text.replace(&format!("{{{{{}}}}}", key), value));
 
-    // tags capitalized
-    for tag in idea.tags {
-        let mut upper = tag.clone();
-        upper.make_ascii_uppercase();
-        result += &(upper + " ");
+    outline.name = fill(&outline.name);
+    outline.description = fill(&outline.description);
+    outline.tags = outline.tags.iter().map(|tag| fill(tag)).collect();
+    for child in &mut outline.children {
+        fill_placeholders(child, values);
     }
+}
+
+fn read_file(filename: &str) -> Result<String> {
+    let mut contents = String::new();
+    File::open(filename)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
 
-    result += &(idea.name + "\n");
+fn file_extension(filename: &str) -> Result<&str> {
+    let dot_index = filename.rfind('.')?;
+    Ok(&filename[dot_index..])
+}
 
-    // description
-    if idea.description.len() > 0 {
-        // TODO tab each line of the description so Markdown lists don't
-        // register as top-level org elements
-        result += &format!("\n{}\n\n", idea.description);
-    }
+fn create_table(conn: &Connection) -> Result<()> {
+    // Create the Idea table in the database if one doesn't exist. 
+    conn.execute("CREATE TABLE IF NOT EXISTS ideas (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL,
+        tags TEXT NOT NULL,
+
+        parent_id INTEGER,
+        child_ids TEXT NOT NULL)", &[])?;
+    Ok(())
+}
+
+fn insert_idea_row(conn: &Connection, idea: &Idea) -> Result<()> {
+    let tags_yaml = tag_vec_to_yaml(idea.tags.clone());
+    let child_ids_yaml = id_vec_to_yaml(idea.child_ids.clone());
+    let parent_id: Value = match idea.parent_id {
+        Some(parent_id) => Value::Integer(parent_id),
+        None => Value::Null,
+    };
+
+    let mut statement = conn.prepare_cached("INSERT INTO ideas (id, name, description, tags, parent_id, child_ids) VALUES (?, ?, ?, ?, ?, ?)")?;
+    let args: &[&ToSql] = &[
+        &idea.id,
+        &idea.name,
+        &idea.description,
+        &tags_yaml,
+        &parent_id,
+        &child_ids_yaml,
+    ];
+    statement.execute(args)?;
+    Ok(())
+}
 
-    // children
-    for id in idea.child_ids {
-        let child = tree.get_idea(id)?;
+fn is_text_mirror_file(path: &Path) -> bool {
+    path.extension().map_or(false, |extension| extension == "dvt")
+}
 
-        result += &idea_to_org_string(child, tree, depth+1)?;
+fn read_text_mirror(path: &Path) -> Result<Vec<Idea>> {
+    if is_text_mirror_file(path) {
+        return match path.exists() {
+            true => text::from_text(&read_file(&path.to_string_lossy())?),
+            false => Ok(Vec::new()),
+        };
     }
 
-    Ok(result)
+    let mut ideas = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.extension().map_or(false, |extension| extension == "idea") {
+            ideas.extend(text::from_text(&read_file(&file.to_string_lossy())?)?);
+        }
+    }
+    ideas.sort_by_key(|idea| idea.id);
+    Ok(ideas)
 }
 
 fn idea_from_row(row: &Row) -> Idea {
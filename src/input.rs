@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use error::{Result, Error};
+
+pub enum InputLine {
+    Line(String),
+    // The user pressed ^C
+    Interrupted,
+    // There is no more input to read
+    Eof,
+}
+
+// Every line a Repl reads, from the main command prompt or from commands
+// that prompt for more input, comes from its InputSource.
+pub trait InputSource {
+    fn read_line(&mut self, prompt: &str) -> Result<InputLine>;
+
+    // Sources that keep a history of input can override this
+    fn add_history_entry(&mut self, _line: &str) { }
+
+    // Whether a user is at a terminal which can be handed over to other
+    // programs, like an editor
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+// Interactive input with line editing and history
+pub struct RustylineInput {
+    editor: Editor<()>,
+}
+
+impl RustylineInput {
+    pub fn new() -> RustylineInput {
+        RustylineInput { editor: Editor::<()>::new() }
+    }
+}
+
+impl InputSource for RustylineInput {
+    fn read_line(&mut self, prompt: &str) -> Result<InputLine> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(InputLine::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(InputLine::Interrupted),
+            Err(ReadlineError::Eof) => Ok(InputLine::Eof),
+            Err(e) => Err(Error::Readline(e)),
+        }
+    }
+
+    fn add_history_entry(&mut self, line: &str) {
+        self.editor.add_history_entry(line);
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+// Scripted input from a queue of lines, which ends when the queue is empty
+pub struct QueueInput {
+    lines: VecDeque<String>,
+}
+
+impl QueueInput {
+    pub fn new(lines: Vec<&str>) -> QueueInput {
+        QueueInput { lines: lines.into_iter().map(|line| line.to_string()).collect() }
+    }
+}
+
+impl InputSource for QueueInput {
+    fn read_line(&mut self, _prompt: &str) -> Result<InputLine> {
+        match self.lines.pop_front() {
+            Some(line) => Ok(InputLine::Line(line)),
+            None => Ok(InputLine::Eof),
+        }
+    }
+}
+
+// Input piped into stdin when it isn't a terminal. Prompts aren't printed,
+// because nobody is there to read them.
+pub struct StdinInput;
+
+impl InputSource for StdinInput {
+    fn read_line(&mut self, _prompt: &str) -> Result<InputLine> {
+        let mut line = String::new();
+        let stdin = io::stdin();
+
+        match stdin.lock().read_line(&mut line)? {
+            0 => Ok(InputLine::Eof),
+            _ => Ok(InputLine::Line(line.trim_right_matches(|c| c == '\n' || c == '\r').to_string())),
+        }
+    }
+}
@@ -1,13 +1,25 @@
 #![feature(try_trait)]
 
 extern crate rusqlite;
-extern crate edit_rs;
 extern crate conv;
 extern crate yaml_rust;
 extern crate rustyline;
+#[macro_use]
+extern crate serde_json;
+extern crate xml;
+extern crate pulldown_cmark;
 
 pub mod error;
 pub mod idea;
 pub mod repl;
+pub mod input;
+pub mod output;
+pub mod formats;
+pub mod git;
+pub mod merge;
+pub mod diff;
+pub mod lock;
+pub mod backup;
+mod editor;
 mod core_commands;
 mod core_printers;
@@ -0,0 +1,126 @@
+// A lock file next to a tree file tells other sessions that the tree is
+// open. While a session runs, a background thread rewrites the lock file
+// every few seconds as a heartbeat, so the lock of a session which crashed
+// goes stale and is ignored:
+//
+//    project.dv.lock
+//    12345         (process id)
+//    1540900000    (time of the last heartbeat, in seconds since the epoch)
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use error::{Result, Error};
+
+pub const HEARTBEAT_SECONDS: u64 = 5;
+// A lock which hasn't had a heartbeat for this long belongs to a dead session
+pub const STALE_SECONDS: u64 = 4 * HEARTBEAT_SECONDS;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub last_heartbeat: u64,
+}
+
+pub fn lock_path(tree_path: &Path) -> PathBuf {
+    let mut lock_path = tree_path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    PathBuf::from(lock_path)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+fn read_holder(lock_path: &Path) -> Option<LockHolder> {
+    let contents = fs::read_to_string(lock_path).ok()?;
+    let mut lines = contents.lines();
+    let pid = lines.next()?.trim().parse().ok()?;
+    let last_heartbeat = lines.next()?.trim().parse().ok()?;
+    Some(LockHolder { pid, last_heartbeat })
+}
+
+fn write_heartbeat(lock_path: &Path) -> Result<()> {
+    fs::write(lock_path, format!("{}\n{}\n", process::id(), now()))?;
+    Ok(())
+}
+
+// The other session which has a tree open, if there is a live one
+pub fn live_session(tree_path: &Path) -> Option<LockHolder> {
+    let holder = read_holder(&lock_path(tree_path))?;
+    if holder.pid == process::id() || now().saturating_sub(holder.last_heartbeat) > STALE_SECONDS {
+        return None;
+    }
+    Some(holder)
+}
+
+pub struct SessionLock {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+impl SessionLock {
+    // Lock a tree for this session, unless another live session has it
+    pub fn acquire(tree_path: &Path) -> Result<SessionLock> {
+        if let Some(holder) = live_session(tree_path) {
+            return Err(Error::DaVinci(format!("{} is open in another session (process {})", tree_path.display(), holder.pid)));
+        }
+
+        // Creating the lock file fails if another session made one since the
+        // check above. A stale lock file is taken over.
+        let path = lock_path(tree_path);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => write!(file, "{}\n{}\n", process::id(), now())?,
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                if let Some(holder) = live_session(tree_path) {
+                    return Err(Error::DaVinci(format!("{} is open in another session (process {})", tree_path.display(), holder.pid)));
+                }
+                write_heartbeat(&path)?;
+            },
+            Err(e) => return Err(e.into()),
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let heartbeat = {
+            let path = path.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut last_heartbeat = now();
+                while !stop.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(250));
+                    if now() >= last_heartbeat + HEARTBEAT_SECONDS {
+                        let _ = write_heartbeat(&path);
+                        last_heartbeat = now();
+                    }
+                }
+            })
+        };
+
+        Ok(SessionLock {
+            path,
+            stop,
+            heartbeat: Some(heartbeat),
+        })
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.join();
+        }
+
+        // Only remove the lock file if no other session took it over
+        if read_holder(&self.path).map_or(false, |holder| holder.pid == process::id()) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
@@ -1,12 +1,24 @@
 #![feature(try_trait)]
 use std::env;
+use std::io;
 use std::path::{Path,PathBuf};
+use std::process;
 
 extern crate dirs;
+extern crate atty;
+extern crate yaml_rust;
+use yaml_rust::Yaml;
 
 extern crate davincibot;
 use davincibot::idea::IdeaTree;
-use davincibot::repl::{Repl, VERSION};
+use davincibot::repl::{self, Repl, VERSION};
+use davincibot::input::{InputSource, RustylineInput, StdinInput, QueueInput};
+use davincibot::error::Error;
+use davincibot::git::{self, GitRepo};
+use davincibot::merge;
+use davincibot::diff;
+use davincibot::lock;
+use davincibot::backup;
 
 fn main() {
     let home_path = dirs::home_dir().unwrap_or(PathBuf::new());
@@ -15,25 +27,246 @@ fn main() {
     let version_commands: Vec<&str> = vec!["-v", "-version", "--v", "--version", ];
 
     let default_tree_file = match Path::new(&format!("{}/yggdrasil/", home_path)).exists() {
-        true => {
-            println!("WARNING! Opening dv file from a repository. Have you pulled?");
-            "yggdrasil/project.dv"
-        },
+        true => "yggdrasil/project.dv",
         false => "project.dv",
     };
     let default_tree_file = format!("{}/{}", home_path, default_tree_file);
 
-    let arg = env::args().skip(1).next().unwrap_or(default_tree_file);
+    // --read-only can come before or after the tree file
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let read_only = args.iter().any(|arg| arg == "--read-only");
+    args.retain(|arg| arg != "--read-only");
+
+    let arg = args.get(0).cloned().unwrap_or(default_tree_file);
     println!("{}", arg);
 
     if arg.chars().next() == Some('-') && version_commands.contains(&arg.as_str()) {
         println!("Da Vinci Bot version: {}", VERSION);
+    } else if arg == "merge" {
+        merge_files(args[1..].to_vec());
+    } else if arg == "diff" {
+        diff_files(args[1..].to_vec());
     } else {
 
+        // Trees in git repositories are checked for changes that haven't
+        // been pulled or committed, and conflicts that haven't been resolved
+        match git::check_before_open(&arg) {
+            Ok(Some(status)) => {
+                if status.behind > 0 {
+                    println!("WARNING! The repository is {} commit(s) behind its upstream. Pull before making changes.", status.behind);
+                }
+                if status.dirty {
+                    println!("WARNING! {} has uncommitted changes.", arg);
+                }
+            },
+            Ok(None) => { },
+            Err(Error::DaVinci(message)) => {
+                println!("{}", message);
+                process::exit(1);
+            },
+            Err(e) => {
+                println!("Error checking git repository: {:?}", e);
+                process::exit(1);
+            },
+        }
+
         println!("Loading Da Vinci file: {}", arg);
-        let mut tree = IdeaTree::open(arg).expect("Failed to create Da Vinci tree."); 
+        let mut tree = open_tree(&arg, read_only);
+        if let Err(e) = tree.use_text_mirror() {
+            println!("Failed to write the text mirror of {}: {:?}", arg, e);
+        }
+        back_up(&tree);
+
+        // Commands can be piped in from a script instead of typed
+        let input: Box<InputSource> = if atty::is(atty::Stream::Stdin) {
+            Box::new(RustylineInput::new())
+        } else {
+            Box::new(StdinInput)
+        };
+
+        let tree_name = repl::default_tree_name(&tree);
+        let mut repl = Repl::new(input);
+        repl.run(&mut tree);
+        // Other trees may have been opened and switched to during the session
+        if let Err(e) = repl.switch_tree(&mut tree, &tree_name) {
+            println!("Failed to switch back to {}: {:?}", arg, e);
+        }
+        back_up(&tree);
+
+        if tree.is_read_only() {
+            return;
+        }
+        if let Ok(Yaml::Boolean(true)) = tree.get_setting(1, "git_commit_on_exit") {
+            if let Some(repo) = GitRepo::containing(&arg) {
+                match repo.commit(&tree, None) {
+                    Ok(Some(message)) => println!("Committed: {}", message.lines().next().unwrap_or("")),
+                    Ok(None) => { },
+                    Err(e) => println!("Failed to commit {}: {:?}", arg, e),
+                }
+            }
+        }
+    }
+}
+
+// Open the tree and lock it for this session, unless --read-only was given.
+// If another session has it open, changes from both could interleave, so
+// the user can open it read-only instead.
+fn open_tree(arg: &str, read_only: bool) -> IdeaTree {
+    if read_only {
+        return IdeaTree::open_read_only(arg).expect("Failed to open Da Vinci tree read-only.");
+    }
+
+    if let Some(holder) = lock::live_session(Path::new(arg)) {
+        println!("{} is open in another session (process {}).", arg, holder.pid);
+        // Piped input is all commands, so there's nobody to ask
+        if !atty::is(atty::Stream::Stdin) {
+            process::exit(1);
+        }
+        println!("Open it read-only? [y/N]");
+        let mut answer = String::new();
+        let _ = io::stdin().read_line(&mut answer);
+        if answer.trim().to_lowercase() != "y" {
+            process::exit(1);
+        }
+        return IdeaTree::open_read_only(arg).expect("Failed to open Da Vinci tree read-only.");
+    }
+
+    let mut tree = IdeaTree::open(arg).expect("Failed to create Da Vinci tree.");
+    if let Err(e) = tree.lock_session() {
+        println!("Failed to lock {}: {:?}", arg, e);
+        process::exit(1);
+    }
+    tree
+}
+
+// Trees are backed up when they're opened and closed, unless they're
+// read-only and can't have changed
+fn back_up(tree: &IdeaTree) {
+    if tree.is_read_only() {
+        return;
+    }
+    if let Err(e) = backup::back_up(tree) {
+        println!("Failed to back up the tree: {:?}", e);
+    }
+}
+
+// davincibot merge base.dv ours.dv theirs.dv -o out.dv [--conflict-ideas] [--json]
+fn merge_files(args: Vec<String>) {
+    let mut files = Vec::new();
+    let mut output = None;
+    let mut conflict_ideas = false;
+    let mut json = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next(),
+            "--conflict-ideas" => conflict_ideas = true,
+            "--json" => json = true,
+            _ => files.push(arg),
+        }
+    }
 
-        Repl::new().run(&mut tree);
+    let output = match output {
+        Some(ref output) if files.len() == 3 => output,
+        _ => {
+            println!("Usage: davincibot merge base.dv ours.dv theirs.dv -o out.dv [--conflict-ideas] [--json]");
+            process::exit(2);
+        },
+    };
+
+    let mut trees = Vec::new();
+    for file in &files {
+        // Opening a file that doesn't exist would create an empty tree
+        if !Path::new(file).exists() {
+            println!("{} doesn't exist", file);
+            process::exit(2);
+        }
+        trees.push(IdeaTree::open(file).unwrap_or_else(|e| {
+            println!("Failed to open {}: {:?}", file, e);
+            process::exit(2);
+        }));
+    }
+
+    let result = merge::merge_trees(&trees[0], &trees[1], &trees[2]).unwrap_or_else(|e| {
+        println!("Failed to merge: {:?}", e);
+        process::exit(2);
+    });
+    let mut merged = IdeaTree::create_from_ideas(output, &result.ideas).unwrap_or_else(|e| {
+        println!("Failed to write {}: {:?}", output, e);
+        process::exit(2);
+    });
+
+    if conflict_ideas && result.conflicts.len() > 0 {
+        if let Err(e) = merge::add_conflict_ideas(&mut merged, &result.conflicts) {
+            println!("Failed to write conflict Ideas: {:?}", e);
+            process::exit(2);
+        }
+    }
+
+    if json {
+        println!("{}", merge::conflicts_to_json(&merged, &result.conflicts));
+    } else {
+        for conflict in &result.conflicts {
+            println!("CONFLICT ({}) in {}: {}", conflict.field, merged.get_path(conflict.id).unwrap_or(String::new()), conflict.message);
+        }
+        println!("Merged into {} with {} conflict(s)", output, result.conflicts.len());
+    }
+
+    // Like git merge, exit with an error while conflicts need resolving
+    if result.conflicts.len() > 0 {
+        process::exit(1);
+    }
+}
+
+// davincibot diff old.dv new.dv [--under expression] [--json]
+fn diff_files(args: Vec<String>) {
+    let mut files = Vec::new();
+    let mut under = None;
+    let mut json = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--under" => under = args.next(),
+            "--json" => json = true,
+            _ => files.push(arg),
+        }
+    }
+    if files.len() != 2 {
+        println!("Usage: davincibot diff old.dv new.dv [--under expression] [--json]");
+        process::exit(2);
+    }
+
+    let mut trees = Vec::new();
+    for file in &files {
+        if !Path::new(file).exists() {
+            println!("{} doesn't exist", file);
+            process::exit(2);
+        }
+        trees.push(IdeaTree::open(file).unwrap_or_else(|e| {
+            println!("Failed to open {}: {:?}", file, e);
+            process::exit(2);
+        }));
+    }
+
+    // Select expressions are evaluated from the root of the new tree
+    let subtree_id = under.map(|expression| {
+        let repl = Repl::new(Box::new(QueueInput::new(vec![])));
+        repl.select_from_expression(&trees[1], &expression).unwrap_or_else(|e| {
+            println!("Failed to select {}: {:?}", expression, e);
+            process::exit(2);
+        })
+    });
+
+    let changes = diff::diff_trees(&trees[0], &trees[1], subtree_id).unwrap_or_else(|e| {
+        println!("Failed to compare trees: {:?}", e);
+        process::exit(2);
+    });
+    if json {
+        println!("{}", diff::changes_to_json(&changes));
+    } else {
+        print!("{}", diff::format_changes(&changes));
     }
 }
 
@@ -0,0 +1,386 @@
+// Three-way merging of trees which were copied from the same base and edited
+// separately. Ideas are matched by id, and each of their fields is merged on
+// its own: a change made on only one side wins, and a field changed the
+// same way on both sides is kept. Tags are merged as sets, so tags added or
+// removed on either side are always merged automatically.
+//
+// SQLite gives a new Idea the id of the newest Idea if that one was deleted,
+// so an Idea which was both renamed and moved on one side is taken to be a
+// new Idea which reused the id, and is given a new one. That's only possible
+// if every Idea of the base with a larger id was deleted on that side too.
+//
+// Anything that can't be merged automatically becomes a MergeConflict, and
+// the merged tree keeps our side's version of it:
+//
+//    * a name, description or parent changed differently on both sides
+//    * an Idea changed on one side and deleted on the other
+//    * moves on both sides which would make an Idea its own ancestor
+//    * two siblings which end up with the same name
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use serde_json::Value;
+
+use idea::{Idea, IdeaTree};
+use error::Result;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    pub id: i64,
+    pub field: String,
+    pub message: String,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+impl MergeConflict {
+    fn new(id: i64, field: &str, message: String) -> MergeConflict {
+        MergeConflict {
+            id,
+            field: field.to_string(),
+            message,
+            base: None,
+            ours: None,
+            theirs: None,
+        }
+    }
+
+    pub fn to_json(&self, tree: &IdeaTree) -> Value {
+        json!({
+            "id": self.id,
+            "path": tree.get_path(self.id).ok(),
+            "field": self.field,
+            "message": self.message,
+            "base": self.base,
+            "ours": self.ours,
+            "theirs": self.theirs,
+        })
+    }
+}
+
+pub fn conflicts_to_json(tree: &IdeaTree, conflicts: &[MergeConflict]) -> String {
+    let conflicts: Vec<Value> = conflicts.iter().map(|conflict| conflict.to_json(tree)).collect();
+    ::serde_json::to_string_pretty(&conflicts).expect("Serialization failed")
+}
+
+pub struct MergeResult {
+    pub ideas: Vec<Idea>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+fn idea_map(tree: &IdeaTree) -> Result<BTreeMap<i64, Idea>> {
+    Ok(tree.all_ideas()?.into_iter().map(|idea| (idea.id, idea)).collect())
+}
+
+pub fn merge_trees(base: &IdeaTree, ours: &IdeaTree, theirs: &IdeaTree) -> Result<MergeResult> {
+    let base = idea_map(base)?;
+    let mut ours = idea_map(ours)?;
+    let mut theirs = idea_map(theirs)?;
+    let mut conflicts = Vec::new();
+
+    renumber_colliding_ids(&base, &mut ours, &mut theirs);
+
+    let all_ids: BTreeSet<i64> = base.keys().chain(ours.keys()).chain(theirs.keys()).cloned().collect();
+    let mut merged = BTreeMap::new();
+    for id in all_ids {
+        let idea = match (base.get(&id), ours.get(&id), theirs.get(&id)) {
+            (Some(base_idea), Some(our_idea), Some(their_idea)) => Some(merge_idea(base_idea, our_idea, their_idea, &mut conflicts)),
+            (None, Some(our_idea), Some(their_idea)) => Some(merge_idea(our_idea, our_idea, their_idea, &mut conflicts)),
+            (None, Some(idea), None) | (None, None, Some(idea)) => Some(idea.clone()),
+            (Some(base_idea), Some(our_idea), None) => keep_if_changed(base_idea, our_idea, "theirs", &mut conflicts),
+            (Some(base_idea), None, Some(their_idea)) => keep_if_changed(base_idea, their_idea, "ours", &mut conflicts),
+            _ => None,
+        };
+
+        if let Some(idea) = idea {
+            merged.insert(id, idea);
+        }
+    }
+
+    restore_deleted_parents(&mut merged, &base, &ours, &theirs, &mut conflicts);
+    break_cycles(&mut merged, &ours, &mut conflicts);
+    rebuild_child_ids(&mut merged, &ours, &theirs);
+    rename_duplicate_siblings(&mut merged, &mut conflicts);
+
+    Ok(MergeResult {
+        ideas: merged.into_iter().map(|(_, idea)| idea).collect(),
+        conflicts,
+    })
+}
+
+// Whether an Idea on one side is the same one as in the base, instead of a
+// new Idea which reused the id of a deleted one
+fn is_same_idea(base_idea: &Idea, idea: &Idea) -> bool {
+    base_idea.name == idea.name || base_idea.parent_id == idea.parent_id
+}
+
+// The ids of Ideas on one side which are new Ideas that reused the id of an
+// Idea in the base
+pub fn reused_ids(base: &BTreeMap<i64, Idea>, side: &BTreeMap<i64, Idea>) -> Vec<i64> {
+    // An id can't have been reused while a larger one was still in use, so
+    // the search stops at the largest id which still holds its base Idea
+    side.iter().rev()
+        .filter_map(|(id, idea)| base.get(id).map(|base_idea| (*id, is_same_idea(base_idea, idea))))
+        .take_while(|&(_, same)| !same)
+        .map(|(id, _)| id)
+        .collect()
+}
+
+// Both sides create Ideas with the next free ids, so different new Ideas can
+// have the same id, on both sides or as an Idea in the base. These new Ideas
+// are given ids nobody has used yet.
+fn renumber_colliding_ids(base: &BTreeMap<i64, Idea>, ours: &mut BTreeMap<i64, Idea>, theirs: &mut BTreeMap<i64, Idea>) {
+    let our_ids = reused_ids(base, ours);
+    let mut their_ids = reused_ids(base, theirs);
+    their_ids.extend(theirs.iter()
+        .filter(|&(id, their_idea)| !base.contains_key(id) && match ours.get(id) {
+            Some(our_idea) => our_idea.name != their_idea.name || our_idea.parent_id != their_idea.parent_id,
+            None => false,
+        })
+        .map(|(id, _)| *id));
+
+    let mut next_id = base.keys().chain(ours.keys()).chain(theirs.keys()).max().cloned().unwrap_or(0) + 1;
+    renumber(ours, &our_ids, &mut next_id);
+    renumber(theirs, &their_ids, &mut next_id);
+}
+
+// Give the Ideas with the given ids on one side the next unused ids
+fn renumber(side: &mut BTreeMap<i64, Idea>, ids: &[i64], next_id: &mut i64) {
+    if ids.is_empty() {
+        return;
+    }
+
+    let mut new_ids = BTreeMap::new();
+    for &id in ids {
+        new_ids.insert(id, *next_id);
+        *next_id += 1;
+    }
+
+    let renumber = |id: i64| *new_ids.get(&id).unwrap_or(&id);
+    let renumbered: BTreeMap<i64, Idea> = side.values().map(|idea| {
+        let mut idea = idea.clone();
+        idea.id = renumber(idea.id);
+        idea.parent_id = idea.parent_id.map(&renumber);
+        idea.child_ids = idea.child_ids.iter().map(|&id| renumber(id)).collect();
+        (idea.id, idea)
+    }).collect();
+    *side = renumbered;
+}
+
+fn merge_idea(base: &Idea, ours: &Idea, theirs: &Idea, conflicts: &mut Vec<MergeConflict>) -> Idea {
+    let mut merged = ours.clone();
+
+    let text = |text: &String| text.clone();
+    let parent = |parent_id: &Option<i64>| parent_id.map(|id| format!("#{}", id)).unwrap_or("none".to_string());
+
+    merged.name = merge_field(base.id, "name", &base.name, &ours.name, &theirs.name, &text, conflicts);
+    merged.description = merge_field(base.id, "description", &base.description, &ours.description, &theirs.description, &text, conflicts);
+    merged.parent_id = merge_field(base.id, "parent", &base.parent_id, &ours.parent_id, &theirs.parent_id, &parent, conflicts);
+    merged.tags = merge_tags(&base.tags, &ours.tags, &theirs.tags);
+
+    merged
+}
+
+fn merge_field<T: Clone + PartialEq>(id: i64, field: &str, base: &T, ours: &T, theirs: &T, to_string: &Fn(&T) -> String, conflicts: &mut Vec<MergeConflict>) -> T {
+    if ours == theirs || theirs == base {
+        ours.clone()
+    } else if ours == base {
+        theirs.clone()
+    } else {
+        let mut conflict = MergeConflict::new(id, field, format!("Both sides changed the {} of Idea #{}", field, id));
+        conflict.base = Some(to_string(base));
+        conflict.ours = Some(to_string(ours));
+        conflict.theirs = Some(to_string(theirs));
+        conflicts.push(conflict);
+        ours.clone()
+    }
+}
+
+// Tags either side added are added, and tags either side removed are removed
+fn merge_tags(base: &Vec<String>, ours: &Vec<String>, theirs: &Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = ours.iter()
+        .filter(|tag| !base.contains(*tag) || theirs.contains(*tag))
+        .cloned()
+        .collect();
+
+    for tag in theirs {
+        if !base.contains(tag) && !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+
+    tags
+}
+
+fn is_changed(base: &Idea, idea: &Idea) -> bool {
+    base.name != idea.name || base.description != idea.description
+        || base.tags != idea.tags || base.parent_id != idea.parent_id
+}
+
+// An Idea deleted on one side stays deleted, unless the other side changed it
+fn keep_if_changed(base: &Idea, idea: &Idea, deleted_by: &str, conflicts: &mut Vec<MergeConflict>) -> Option<Idea> {
+    if !is_changed(base, idea) {
+        return None;
+    }
+
+    conflicts.push(MergeConflict::new(idea.id, "deleted", format!("Idea #{} was deleted by {} but changed by the other side, so it was kept", idea.id, deleted_by)));
+    Some(idea.clone())
+}
+
+// When one side deletes an Idea and the other adds or moves a child into it,
+// the deleted Idea comes back so the child isn't lost
+fn restore_deleted_parents(merged: &mut BTreeMap<i64, Idea>, base: &BTreeMap<i64, Idea>, ours: &BTreeMap<i64, Idea>, theirs: &BTreeMap<i64, Idea>, conflicts: &mut Vec<MergeConflict>) {
+    loop {
+        let missing: Vec<(i64, i64)> = merged.values()
+            .filter_map(|idea| match idea.parent_id {
+                Some(parent_id) if !merged.contains_key(&parent_id) => Some((idea.id, parent_id)),
+                _ => None,
+            })
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        for (child_id, parent_id) in missing {
+            if merged.contains_key(&parent_id) {
+                continue;
+            }
+
+            let restored = ours.get(&parent_id).or(theirs.get(&parent_id)).or(base.get(&parent_id)).cloned();
+            match restored {
+                Some(parent) => {
+                    conflicts.push(MergeConflict::new(parent_id, "deleted", format!("Idea #{} was deleted, but was restored because Idea #{} is still its child", parent_id, child_id)));
+                    merged.insert(parent_id, parent);
+                },
+                None => {
+                    // The parent never existed anywhere, so the child goes to the root
+                    merged.get_mut(&child_id).unwrap().parent_id = Some(1);
+                },
+            }
+        }
+    }
+}
+
+// Moves on both sides can make an Idea its own ancestor. Our side's parents
+// are used for Ideas in a cycle until it's broken.
+fn break_cycles(merged: &mut BTreeMap<i64, Idea>, ours: &BTreeMap<i64, Idea>, conflicts: &mut Vec<MergeConflict>) {
+    let ids: Vec<i64> = merged.keys().cloned().collect();
+
+    for id in ids {
+        loop {
+            let cycle = find_cycle(merged, id);
+            let cycle = match cycle {
+                Some(cycle) => cycle,
+                None => break,
+            };
+
+            // Prefer reverting an Idea which their side moved
+            let reverted_id = cycle.iter().cloned()
+                .find(|cycle_id| ours.get(cycle_id).map(|idea| idea.parent_id) != Some(merged[cycle_id].parent_id))
+                .unwrap_or(cycle[0]);
+            let parent_id = match ours.get(&reverted_id) {
+                Some(our_idea) if our_idea.parent_id != merged[&reverted_id].parent_id => our_idea.parent_id,
+                _ => Some(1),
+            };
+
+            conflicts.push(MergeConflict::new(reverted_id, "parent", format!("Moving Idea #{} would make it its own ancestor, so it was left where our side put it", reverted_id)));
+            merged.get_mut(&reverted_id).unwrap().parent_id = parent_id;
+        }
+    }
+}
+
+fn find_cycle(merged: &BTreeMap<i64, Idea>, id: i64) -> Option<Vec<i64>> {
+    let mut path = Vec::new();
+    let mut visited = HashSet::new();
+    let mut current = Some(id);
+
+    while let Some(current_id) = current {
+        if !visited.insert(current_id) {
+            let start = path.iter().position(|&path_id| path_id == current_id).unwrap();
+            return Some(path[start..].to_vec());
+        }
+        path.push(current_id);
+        current = merged.get(&current_id).and_then(|idea| idea.parent_id);
+    }
+
+    None
+}
+
+// Children are kept in our order, then in theirs, then in order of id
+fn rebuild_child_ids(merged: &mut BTreeMap<i64, Idea>, ours: &BTreeMap<i64, Idea>, theirs: &BTreeMap<i64, Idea>) {
+    let mut children: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+    for idea in merged.values() {
+        if let Some(parent_id) = idea.parent_id {
+            children.entry(parent_id).or_insert(Vec::new()).push(idea.id);
+        }
+    }
+
+    for idea in merged.values_mut() {
+        let unordered = children.remove(&idea.id).unwrap_or(Vec::new());
+
+        let mut child_ids = Vec::new();
+        let orders = [ours.get(&idea.id), theirs.get(&idea.id)];
+        for order in orders.iter().filter_map(|side| side.map(|side_idea| &side_idea.child_ids)) {
+            for child_id in order {
+                if unordered.contains(child_id) && !child_ids.contains(child_id) {
+                    child_ids.push(*child_id);
+                }
+            }
+        }
+        for child_id in unordered {
+            if !child_ids.contains(&child_id) {
+                child_ids.push(child_id);
+            }
+        }
+
+        idea.child_ids = child_ids;
+    }
+}
+
+fn rename_duplicate_siblings(merged: &mut BTreeMap<i64, Idea>, conflicts: &mut Vec<MergeConflict>) {
+    let parents: Vec<(i64, Vec<i64>)> = merged.values().map(|idea| (idea.id, idea.child_ids.clone())).collect();
+
+    for (parent_id, child_ids) in parents {
+        let mut names = HashSet::new();
+        for child_id in child_ids {
+            let child = merged.get_mut(&child_id).unwrap();
+            if names.insert(child.name.clone()) {
+                continue;
+            }
+
+            let new_name = format!("{} (conflict #{})", child.name, child_id);
+            conflicts.push(MergeConflict::new(child_id, "name", format!("Idea #{} has another child named '{}', so Idea #{} was renamed", parent_id, child.name, child_id)));
+            names.insert(new_name.clone());
+            child.name = new_name;
+        }
+    }
+}
+
+// Write conflicts into the tree as Ideas tagged conflict under an Idea called
+// "Merge conflicts", for resolving by hand. The Idea left by an earlier merge
+// is reused.
+pub fn add_conflict_ideas(tree: &mut IdeaTree, conflicts: &[MergeConflict]) -> Result<i64> {
+    let conflicts_id = match tree.find_child(1, "Merge conflicts")? {
+        Some(conflicts_id) => conflicts_id,
+        None => tree.create_idea(1, "Merge conflicts".to_string(), None)?,
+    };
+    tree.add_tags(conflicts_id, vec!["conflict".to_string()])?;
+
+    for conflict in conflicts {
+        let name = format!("{} of #{} {}", conflict.field, conflict.id, tree.get_path(conflict.id)?);
+        let conflict_id = match tree.find_child(conflicts_id, &name)? {
+            Some(conflict_id) => conflict_id,
+            None => tree.create_idea(conflicts_id, name, None)?,
+        };
+        tree.add_tags(conflict_id, vec!["conflict".to_string()])?;
+
+        let mut description = conflict.message.clone();
+        if let (Some(ours), Some(theirs)) = (conflict.ours.as_ref(), conflict.theirs.as_ref()) {
+            description += &format!("\n\n<<<<<<< ours\n{}\n=======\n{}\n>>>>>>> theirs", ours, theirs);
+        }
+        tree.set_description(conflict_id, &description)?;
+    }
+
+    Ok(conflicts_id)
+}
@@ -0,0 +1,69 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// Everything a Repl prints goes through its OutputSink, so the output can be
+// captured in tests, written to a file, or displayed by another program that
+// embeds the Repl.
+pub trait OutputSink {
+    fn print(&mut self, text: &str);
+
+    fn println(&mut self, text: &str) {
+        self.print(text);
+        self.print("\n");
+    }
+}
+
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn print(&mut self, text: &str) {
+        print!("{}", text);
+        // Partial lines need to show up before the next prompt
+        io::stdout().flush().expect("Failed to flush stdout");
+    }
+}
+
+pub struct FileSink {
+    file: File,
+}
+
+impl FileSink {
+    pub fn new(file: File) -> FileSink {
+        FileSink { file }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn print(&mut self, text: &str) {
+        self.file.write_all(text.as_bytes()).expect("Failed to write output to file");
+    }
+}
+
+// A BufferSink collects output in memory. Clones share the same buffer, so
+// one clone can be given to a Repl and the other used to read the transcript.
+#[derive(Clone)]
+pub struct BufferSink {
+    buffer: Rc<RefCell<String>>,
+}
+
+impl BufferSink {
+    pub fn new() -> BufferSink {
+        BufferSink { buffer: Rc::new(RefCell::new(String::new())) }
+    }
+
+    pub fn contents(&self) -> String {
+        self.buffer.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.buffer.borrow_mut().clear();
+    }
+}
+
+impl OutputSink for BufferSink {
+    fn print(&mut self, text: &str) {
+        self.buffer.borrow_mut().push_str(text);
+    }
+}
@@ -1,15 +1,15 @@
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::borrow::Borrow;
-use std::process::exit;
-
-use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use std::mem;
 
 use yaml_rust::Yaml;
 
 use idea::{IdeaTree, Idea};
 use error::{Result, Error};
+use input::{InputSource, InputLine};
+use editor;
+use output::{OutputSink, StdoutSink};
 
 use core_commands::core_commands;
 use core_printers::core_printers;
@@ -40,7 +40,7 @@ impl CommandArgs {
 }
 
 // TODO this won't be pub after printing is moved out of core_commands.rs into repl.rs
-pub type PrinterImplementation = Fn(&Idea, &IdeaTree) -> Result<()>;
+pub type PrinterImplementation = Fn(&Idea, &IdeaTree, &mut OutputSink) -> Result<()>;
 
 pub struct IdeaPrinter {
     pub always_inherited: bool, 
@@ -49,7 +49,7 @@ pub struct IdeaPrinter {
 
 impl IdeaPrinter {
     pub fn new<C>(always_inherited: bool, implementation: C) -> Self
-        where C: 'static + Fn(&Idea, &IdeaTree) -> Result<()>
+        where C: 'static + Fn(&Idea, &IdeaTree, &mut OutputSink) -> Result<()>
     {
         IdeaPrinter {
             always_inherited,
@@ -60,15 +60,37 @@ impl IdeaPrinter {
 
 
 
-type CommandImplementation = Fn(&mut Repl, &mut IdeaTree, Vec<String>) -> Result<()>;
+// Every command returns a result set of Idea ids (which may be empty) so
+// that commands can be composed with the | operator
+type CommandImplementation = Fn(&mut Repl, &mut IdeaTree, Vec<String>) -> Result<Vec<i64>>;
 
-pub struct CommandHandler(CommandArgs, Rc<CommandImplementation>);
+// A handler which is marked as mutating changes the tree, so it can't be
+// used while the tree is open read-only
+pub struct CommandHandler(CommandArgs, Rc<CommandImplementation>, bool);
 
 impl CommandHandler {
+    // Create a handler for a command that only has side effects
     pub fn new<C>(args: CommandArgs, implementation: C) -> Self
         where C: 'static + Fn(&mut Repl, &mut IdeaTree, Vec<String>) -> Result<()>
     {
-        CommandHandler (args, Rc::new(implementation))
+        CommandHandler (args, Rc::new(move |repl: &mut Repl, tree: &mut IdeaTree, args: Vec<String>| {
+            implementation(repl, tree, args)?;
+            Ok(Vec::new())
+        }), false)
+    }
+
+    // Create a handler for a command that returns a result set of Ideas
+    // which can be piped into another command
+    pub fn returning<C>(args: CommandArgs, implementation: C) -> Self
+        where C: 'static + Fn(&mut Repl, &mut IdeaTree, Vec<String>) -> Result<Vec<i64>>
+    {
+        CommandHandler (args, Rc::new(implementation), false)
+    }
+
+    // Mark the handler as one that changes the tree
+    pub fn mutating(mut self) -> Self {
+        self.2 = true;
+        self
     }
 }
 
@@ -78,11 +100,34 @@ pub struct Command {
     pub handlers: Vec<CommandHandler>,
 }
 
+// A tree opened alongside the one commands are running on. Switching to it
+// swaps the two, along with their selections.
+struct MountedTree {
+    name: String,
+    tree: IdeaTree,
+    selected_id_stack: Vec<i64>,
+}
+
 pub struct Repl {
     selected_id_stack: Vec<i64>,
-    rl: Editor<()>,
+    // Other trees open in this session, and the name of the current tree if
+    // it has been given one
+    mounted_trees: Vec<MountedTree>,
+    tree_name: Option<String>,
+    // The selected Idea and its path after the last command, to tell if
+    // another session moves or deletes it
+    last_selection: Option<(i64, String)>,
+    input: Box<InputSource>,
+    output: Box<OutputSink>,
     commands: HashMap<String, Command>,
     printers: HashMap<String, IdeaPrinter>,
+    // True while the result set of the running command will be piped into
+    // another command
+    piped: bool,
+    // Names of the macros currently being expanded, to catch recursion
+    expanding_macros: Vec<String>,
+    // True once the input source has run out
+    quit: bool,
 }
 
 impl Repl {
@@ -90,6 +135,16 @@ impl Repl {
         self.selected_id_stack.last().cloned().unwrap_or(1)
     }
 
+    // Commands which would otherwise prompt the user to choose from their
+    // results should check this and skip the prompt when piped
+    pub fn is_piped(&self) -> bool {
+        self.piped
+    }
+
+    pub fn output(&mut self) -> &mut OutputSink {
+        &mut *self.output
+    }
+
     pub fn select(&mut self, id: i64, tree: &IdeaTree) -> Result<()> {
         // TODO validate that the ID is valid, not out of range
         self.selected_id_stack.push(id);
@@ -98,12 +153,23 @@ impl Repl {
         Ok(())
     }
 
-    pub fn new() -> Repl {
+    pub fn new(input: Box<InputSource>) -> Repl {
+        Repl::with_output(input, Box::new(StdoutSink))
+    }
+
+    pub fn with_output(input: Box<InputSource>, output: Box<OutputSink>) -> Repl {
         let mut repl = Repl { 
             selected_id_stack: vec![],
+            mounted_trees: vec![],
+            tree_name: None,
+            last_selection: None,
             commands: HashMap::new(),
             printers: HashMap::new(),
-            rl: Editor::<()>::new(),
+            input,
+            output,
+            piped: false,
+            expanding_macros: vec![],
+            quit: false,
         };
         // TODO set up rl history file
         // TODO add commands to history log
@@ -118,9 +184,9 @@ impl Repl {
     pub fn register_commands(&mut self, commands: HashMap<String, Command>) {
         for (command, handler_list) in commands {
             if self.commands.contains_key(&command) {
-                println!("Error! Cannot add duplicate command with name '{}'", command);
+                self.output.println(&format!("Error! Cannot add duplicate command with name '{}'", command));
             } else if command.len() == 1 {
-                println!("Error! Commands cannot have single-character names.");
+                self.output.println("Error! Commands cannot have single-character names.");
             } else {
                 self.commands.insert(command, handler_list);
             }
@@ -130,7 +196,7 @@ impl Repl {
     pub fn register_printers(&mut self, printers: HashMap<String, IdeaPrinter>) {
         for (idea_type, printer) in printers {
             if self.printers.contains_key(&idea_type) {
-                println!("Error! Cannot add duplicate printer for type '{}'", idea_type);
+                self.output.println(&format!("Error! Cannot add duplicate printer for type '{}'", idea_type));
             } else {
                 self.printers.insert(idea_type, printer);
             }
@@ -142,32 +208,33 @@ impl Repl {
     pub fn prompt<C>(&mut self, prefix: &str, mut callback: C, add_history: bool)
         where C: FnMut(&mut Repl, &str) -> Result<bool>
     {
-        loop {
+        // Once the input source runs out, every prompt ends immediately
+        while !self.quit {
             // TODO if add_history is false, don't allow up and down
             // to reuse lines from history, either.
-            let input = self.rl.readline(&format!("{} ", prefix));
+            let input = self.input.read_line(&format!("{} ", prefix));
             match input {
-                Ok(input) => {
+                Ok(InputLine::Line(input)) => {
                     let mut line = input.trim();
                     if line == "exit" {
                         break;
                     }
                     else {
                         if add_history {
-                            self.rl.add_history_entry(line);
+                            self.input.add_history_entry(line);
                         }
 
                         match callback(self, &mut line) {
                             Ok(true) => { },
                             Ok(false) => break,
-                            Err(e) => println!("Error processing console input: {:?}", e),
+                            Err(e) => self.output.println(&format!("Error processing console input: {:?}", e)),
                         }
                     }
                 },
-                Err(ReadlineError::Interrupted) => break,
-                Err(ReadlineError::Eof) => exit(0),
+                Ok(InputLine::Interrupted) => break,
+                Ok(InputLine::Eof) => self.quit = true,
                 Err(e) => {
-                    println!("Error getting console input: {:?}", e);
+                    self.output.println(&format!("Error getting console input: {:?}", e));
                     continue
                 },
             };
@@ -191,19 +258,33 @@ impl Repl {
         Ok(arg_values)
     }
 
+    // Let the user edit a value in their $VISUAL or $EDITOR, in a file with
+    // the given extension. Without an editor, or a terminal to run one in,
+    // fall back to prompting for a single line.
+    pub fn edit_text(&mut self, label: &str, text: &str, extension: &str) -> Result<String> {
+        if self.input.is_interactive() {
+            if let Some(editor_command) = editor::editor_command() {
+                return editor::edit_text(&editor_command, text, extension);
+            }
+        }
+
+        self.output.println(&format!("Current {}: {}", label, text));
+        Ok(self.prompt_for_args(vec![label])?.remove(0))
+    }
+
     pub fn prompt_to_select_from(&mut self, ideas: &Vec<Idea>, tree: &IdeaTree) {
         for (idx, idea) in ideas.iter().enumerate() {
-            println!("{}. {} (#{})", idx+1, idea.format_name_with_tags(), idea.id);
+            self.output.println(&format!("{}. {} (#{})", idx+1, idea.format_name_with_tags(), idea.id));
         }
 
         if let Ok(args) = self.prompt_for_args(vec![&"select one?"]) {
             if let Ok(index) = args[0].parse::<usize>() {
                 if index == 0 || index > ideas.len() {
-                    println!("{} is not a search result.", index);
+                    self.output.println(&format!("{} is not a search result.", index));
                 }
                 else if let Some(idea) = ideas.get(index-1) {
                     if let Err(e) = self.select(idea.id, tree) {
-                        println!("Error: {:?}", e);
+                        self.output.println(&format!("Error: {:?}", e));
                     }
                 }
             }
@@ -224,14 +305,9 @@ impl Repl {
         }, true); // Do save commands in the history file
     }
 
-    // TODO this is a janky helper function that doesn't account for terminal width
-    fn print_hr(&self) {
-        println!("--------------");
-    }
-
     // TODO Ideas should be printed in a prettier form somehow, with line
     // wrapping
-    pub fn print(&self, tree: &IdeaTree, limited_description: bool) -> Result<()> {
+    pub fn print(&mut self, tree: &IdeaTree, limited_description: bool) -> Result<()> {
         let idea = tree.get_idea(self.selected_id())?;
 
         let description_limit = if limited_description {
@@ -257,16 +333,16 @@ impl Repl {
             idea.description.len()
         };
 
-        self.print_hr();
+        print_hr(&mut *self.output);
         // TODO check max_name and shorten name printing
-        println!("#{}: {}", idea.id, idea.name);
+        self.output.println(&format!("#{}: {}", idea.id, idea.name));
         if idea.tags.len() > 0 {
             for tag in &idea.tags {
-                print!("[{}] ", tag);
+                self.output.print(&format!("[{}] ", tag));
             }
-            println!();
+            self.output.println("");
         }
-        self.print_hr();
+        print_hr(&mut *self.output);
 
         if idea.description.len() > 0 {
             let description_to_print = if description_limit < idea.description.len() {
@@ -277,15 +353,15 @@ impl Repl {
                 idea.description.clone()
             };
 
-            println!("{}", description_to_print);
-            self.print_hr();
+            self.output.println(&description_to_print);
+            print_hr(&mut *self.output);
         }
 
 
         if idea.child_ids.len() > 0 {
-            println!("{} children", idea.child_ids.len()); // TODO print how many are hidden
+            self.output.println(&format!("{} children", idea.child_ids.len())); // TODO print how many are hidden
             // TODO and also truncate children
-            self.print_hr();
+            print_hr(&mut *self.output);
         }
 
         // do special printing using registered Idea type printers
@@ -294,18 +370,124 @@ impl Repl {
             let printer_implementation: &PrinterImplementation = idea_printer.implementation.borrow();
 
             if tree.get_tags(self.selected_id(), always_inherited)?.contains(&idea_type) {
-                (*printer_implementation)(&idea, tree)?;
-                self.print_hr();
+                (*printer_implementation)(&idea, tree, &mut *self.output)?;
+                print_hr(&mut *self.output);
             }
         }
 
         Ok(())
     }
 
-    pub fn run_command(&mut self, tree: &mut IdeaTree, input_line: String) {
+    // Run a line of input, which may chain multiple commands with ; and pipe
+    // the result set of one command into the next with |. Returns the result
+    // set of the last command that ran.
+    pub fn run_command(&mut self, tree: &mut IdeaTree, input_line: String) -> Vec<i64> {
+        if let Err(e) = self.refresh_selection(tree) {
+            self.output.println(&format!("Error checking for changes from other sessions: {:?}", e));
+        }
+
+        let results = match self.run_chain(tree, &input_line) {
+            Ok(results) => results,
+            Err(Error::DaVinci(message)) => {
+                self.output.println(&message);
+                Vec::new()
+            },
+            Err(e) => {
+                self.output.println(&format!("Error: {:?}", e));
+                Vec::new()
+            },
+        };
+
+        let selected_id = self.selected_id();
+        self.last_selection = tree.get_path(selected_id).ok().map(|path| (selected_id, path));
+        results
+    }
+
+    // If another session changed the tree since the last command, drop Ideas
+    // it deleted from the selection stack, and warn if the selected Idea was
+    // moved or deleted
+    fn refresh_selection(&mut self, tree: &mut IdeaTree) -> Result<()> {
+        if !tree.changed_externally()? {
+            return Ok(());
+        }
+
+        self.selected_id_stack.retain(|&id| tree.get_idea(id).is_ok());
+
+        if let Some((id, ref old_path)) = self.last_selection {
+            match tree.get_path(id) {
+                Err(_) => {
+                    let message = format!("The selected Idea #{} was deleted in another session. Selected #{} instead.", id, self.selected_id());
+                    self.output.println(&message);
+                },
+                Ok(ref path) if path != old_path => {
+                    let message = format!("The selected Idea #{} was moved to {} in another session.", id, path);
+                    self.output.println(&message);
+                },
+                Ok(_) => { },
+            }
+        }
+        Ok(())
+    }
+
+    fn run_chain(&mut self, tree: &mut IdeaTree, line: &str) -> Result<Vec<i64>> {
+        let mut results = Vec::new();
+
+        // Chained commands run in order, but an error stops the rest of the
+        // chain from running on an unexpected selection
+        for chained_line in split_unescaped(line, ';') {
+            results = self.run_pipeline(tree, chained_line.trim())?;
+        }
+
+        Ok(results)
+    }
+
+    fn run_pipeline(&mut self, tree: &mut IdeaTree, line: &str) -> Result<Vec<i64>> {
+        let stages = split_unescaped(line, '|');
+        let last_stage = stages.len() - 1;
+
+        let mut results = Vec::new();
+        for (stage_idx, stage) in stages.iter().enumerate() {
+            let stage = stage.trim();
+
+            let was_piped = self.piped;
+            self.piped = was_piped || stage_idx < last_stage;
+
+            let stage_results = if stage_idx == 0 {
+                self.execute(tree, stage)
+            } else if results.len() == 0 {
+                Err(Error::DaVinci(format!("There are no Ideas to pipe into '{}'", stage)))
+            } else {
+                // The piped command runs once with each Idea from the
+                // previous result set selected
+                let mut stage_results = Vec::new();
+                let stack_depth = self.selected_id_stack.len();
+                for id in results {
+                    self.selected_id_stack.push(id);
+                    let id_results = self.execute(tree, stage);
+                    self.selected_id_stack.truncate(stack_depth);
+
+                    match id_results {
+                        Ok(id_results) => stage_results.extend(id_results),
+                        Err(e) => {
+                            self.piped = was_piped;
+                            return Err(e);
+                        },
+                    }
+                }
+                Ok(stage_results)
+            };
+
+            self.piped = was_piped;
+            results = stage_results?;
+        }
+
+        Ok(results)
+    }
+
+    fn execute(&mut self, tree: &mut IdeaTree, input_line: &str) -> Result<Vec<i64>> {
         // An empty query is a no-op
         if input_line.len() == 0 {
-            return;
+            return Ok(Vec::new());
         }
 
         // The first token of every input line should be a valid command name
@@ -318,26 +500,22 @@ impl Repl {
                     if let Some(shortcuts_yaml) = shortcuts.get_yaml_data().unwrap_or(None) {
                         match &shortcuts_yaml[command.as_str()] {
                             Yaml::BadValue => {
-                                println!("Error! No command for shortcut '{}'", command);
-                                return;
+                                return Err(Error::DaVinci(format!("Error! No command for shortcut '{}'", command)));
                             },
                             Yaml::String(shortcut_command) => {
                                 shortcut_command.clone()
                             },
                             _ => {
-                                println!("Error! Command for shortcut '{}' is not a string!", command);
-                                return;
+                                return Err(Error::DaVinci(format!("Error! Command for shortcut '{}' is not a string!", command)));
                             },
                         }
                     }
                     else {
-                        println!("Error! The description of the shortcuts meta idea #{} is not properly formatted YAML", shortcuts.id);
-                        return;
+                        return Err(Error::DaVinci(format!("Error! The description of the shortcuts meta idea #{} is not properly formatted YAML", shortcuts.id)));
                     }
                 },
                 None => {
-                    println!("Error! No command shortcuts are defined.");
-                    return;
+                    return Err(Error::DaVinci("Error! No command shortcuts are defined.".to_string()));
                 }, 
             };
         }
@@ -346,6 +524,7 @@ impl Repl {
 
             let args;
             let mut handler: Option<Rc<CommandImplementation>> = None;
+            let mut mutating = false;
 
             {
                 let handler_list = &self.commands[&command];
@@ -364,23 +543,94 @@ impl Repl {
                 for possible_handler in &handler_list.handlers {
                     if possible_handler.0.matches((&args).len()) {
                         handler = Some(Rc::clone(&possible_handler.1));
+                        mutating = possible_handler.2;
                         break;
                     }
                 }
             }
 
+            if mutating && tree.is_read_only() {
+                return Err(Error::DaVinci(format!("Can't use '{}' because {} is open read-only", command, self.tree_name(tree))));
+            }
+
             match handler {
                 Some(handler) => {
-                    if let Err(e) = (*handler)(self, tree, args) {
-                        println!("'{}' command returned an error: {:?}", command, e);
+                    match (*handler)(self, tree, args) {
+                        Ok(results) => Ok(results),
+                        Err(e) => Err(Error::DaVinci(format!("'{}' command returned an error: {:?}", command, e))),
                     }
                 },
-                None => println!("Can't call '{}' command with {} arguments", command, args.len()),
+                None => Err(Error::DaVinci(format!("Can't call '{}' command with {} arguments", command, args.len()))),
             }
         }
+        else if let Some(macro_lines) = self.expand_macro(tree, &command, parts.next().unwrap_or(""))? {
+            self.run_macro(tree, command, macro_lines)
+        }
         else {
-            println!("There is no Da Vinci Bot command named {}", command);
+            Err(Error::DaVinci(format!("There is no Da Vinci Bot command named {}", command)))
+        }
+    }
+
+    // Look up a macro in the nearest .macros meta Idea, and substitute the
+    // given arguments into its command lines. Macros are defined in YAML as
+    // either one command line or a list of them:
+    //
+    //    read: tag reading; move $1 -> Books
+    //    book:
+    //      - add Books/$*
+    //      - tag unread
+    //
+    // $1, $2, etc. are replaced by individual arguments, $* by all of them,
+    // and $$ by a literal $.
+    fn expand_macro(&self, tree: &IdeaTree, name: &str, args: &str) -> Result<Option<Vec<String>>> {
+        let macros_yaml = match tree.get_meta_yaml(self.selected_id(), &"macros")? {
+            Some(macros_yaml) => macros_yaml,
+            None => return Ok(None),
+        };
+
+        let macro_lines = match &macros_yaml[name] {
+            Yaml::BadValue => return Ok(None),
+            Yaml::String(line) => vec![line.clone()],
+            Yaml::Array(lines) => {
+                let mut macro_lines = Vec::new();
+                for line in lines {
+                    match line.as_str() {
+                        Some(line) => macro_lines.push(line.to_string()),
+                        None => return Err(Error::DaVinci(format!("Error! Macro '{}' contains a line which is not a string!", name))),
+                    }
+                }
+                macro_lines
+            },
+            _ => return Err(Error::DaVinci(format!("Error! Macro '{}' is not a string or a list of strings!", name))),
+        };
+
+        let args = args.trim();
+        let split_args: Vec<&str> = args.split_whitespace().collect();
+
+        let mut expanded_lines = Vec::new();
+        for line in macro_lines {
+            expanded_lines.push(substitute_macro_args(name, &line, args, &split_args)?);
         }
+
+        Ok(Some(expanded_lines))
+    }
+
+    fn run_macro(&mut self, tree: &mut IdeaTree, name: String, macro_lines: Vec<String>) -> Result<Vec<i64>> {
+        if self.expanding_macros.contains(&name) {
+            return Err(Error::DaVinci(format!("Error! Macro '{}' expands into itself ({} -> {})", name, self.expanding_macros.join(" -> "), name)));
+        }
+
+        self.expanding_macros.push(name);
+        let mut results = Ok(Vec::new());
+        for line in macro_lines {
+            results = self.run_chain(tree, &line);
+            if results.is_err() {
+                break;
+            }
+        }
+        self.expanding_macros.pop();
+
+        results
     }
 
     // TODO find out how Git checks for commands with similar names to typos,
@@ -443,23 +693,228 @@ impl Repl {
         }
     }
 
-    pub fn select_from_expression(&self, tree: &IdeaTree, expression: &str) -> Result<i64> {
-        let mut temp_selected = self.selected_id();
+    fn select_from_path(selected_id: i64, tree: &IdeaTree, expression: &str) -> Result<i64> {
+        let mut temp_selected = selected_id;
         for part in expression.split_terminator('/') {
             temp_selected = Repl::select_from_expression_internal(temp_selected, tree, part)?;
         }
         Ok(temp_selected)
     }
 
-    pub fn print_help(&self) {
+    pub fn select_from_expression(&self, tree: &IdeaTree, expression: &str) -> Result<i64> {
+        match self.split_tree_prefix(tree, expression) {
+            (Some(name), _) => Err(Error::DaVinci(format!("'{}' is in tree '{}'. Switch to it first.", expression, name))),
+            (None, expression) => Repl::select_from_path(self.selected_id(), tree, expression),
+        }
+    }
+
+    // Select an Idea in any open tree. Expressions can start with the name of
+    // a tree, like work:#12, and are evaluated from that tree's selection.
+    // The tree name returned is None for the current tree.
+    pub fn select_in_any_tree(&self, tree: &IdeaTree, expression: &str) -> Result<(Option<String>, i64)> {
+        match self.split_tree_prefix(tree, expression) {
+            (Some(name), expression) => {
+                let mounted = self.mounted_tree(&name)?;
+                let selected_id = mounted.selected_id_stack.last().cloned().unwrap_or(1);
+                Ok((Some(name.clone()), Repl::select_from_path(selected_id, &mounted.tree, expression)?))
+            },
+            (None, expression) => Ok((None, Repl::select_from_path(self.selected_id(), tree, expression)?)),
+        }
+    }
+
+    // Separate the tree name from a select expression, if it starts with the
+    // name of an open tree. A prefix naming the current tree is dropped.
+    fn split_tree_prefix<'a>(&self, tree: &IdeaTree, expression: &'a str) -> (Option<String>, &'a str) {
+        if let Some(colon) = expression.find(':') {
+            let name = &expression[..colon];
+            let rest = &expression[colon + 1..];
+            if self.mounted_trees.iter().any(|mounted| mounted.name == name) {
+                return (Some(name.to_string()), rest);
+            }
+            if name == self.tree_name(tree) {
+                return (None, rest);
+            }
+        }
+
+        (None, expression)
+    }
+
+    // The name of the tree commands are running on: the name it was opened
+    // under, or else the name of its file
+    pub fn tree_name(&self, tree: &IdeaTree) -> String {
+        match self.tree_name {
+            Some(ref name) => name.clone(),
+            None => default_tree_name(tree),
+        }
+    }
+
+    pub fn tree_names(&self, tree: &IdeaTree) -> Vec<String> {
+        let mut names = vec![self.tree_name(tree)];
+        names.extend(self.mounted_trees.iter().map(|mounted| mounted.name.clone()));
+        names
+    }
+
+    fn mounted_tree(&self, name: &str) -> Result<&MountedTree> {
+        match self.mounted_trees.iter().find(|mounted| mounted.name == name) {
+            Some(mounted) => Ok(mounted),
+            None => Err(Error::DaVinci(format!("No tree is open under the name '{}'", name))),
+        }
+    }
+
+    // Get either the current tree (when the name is None) or an open tree
+    pub fn tree_mut<'a>(&'a mut self, tree: &'a mut IdeaTree, name: &Option<String>) -> Result<&'a mut IdeaTree> {
+        match *name {
+            None => Ok(tree),
+            Some(ref name) => match self.mounted_trees.iter_mut().find(|mounted| &mounted.name == name) {
+                Some(mounted) => Ok(&mut mounted.tree),
+                None => Err(Error::DaVinci(format!("No tree is open under the name '{}'", name))),
+            },
+        }
+    }
+
+    // Open another tree in this session under the given name
+    pub fn mount_tree(&mut self, tree: &IdeaTree, name: String, mounted: IdeaTree) -> Result<()> {
+        if name.len() == 0 || name.contains(':') || name.contains('/') {
+            return Err(Error::DaVinci(format!("'{}' can't be used as a tree name", name)));
+        }
+        if self.tree_names(tree).contains(&name) {
+            return Err(Error::DaVinci(format!("A tree is already open under the name '{}'", name)));
+        }
+
+        self.mounted_trees.push(MountedTree {
+            name,
+            tree: mounted,
+            selected_id_stack: vec![],
+        });
+        Ok(())
+    }
+
+    // Close an open tree. The current tree can't be closed.
+    pub fn unmount_tree(&mut self, tree: &IdeaTree, name: &str) -> Result<IdeaTree> {
+        if name == self.tree_name(tree) {
+            return Err(Error::DaVinci(format!("Can't close '{}' because it's the current tree", name)));
+        }
+
+        match self.mounted_trees.iter().position(|mounted| mounted.name == name) {
+            Some(idx) => Ok(self.mounted_trees.remove(idx).tree),
+            None => Err(Error::DaVinci(format!("No tree is open under the name '{}'", name))),
+        }
+    }
+
+    // Make an open tree the one commands run on
+    pub fn switch_tree(&mut self, tree: &mut IdeaTree, name: &str) -> Result<()> {
+        let current_name = self.tree_name(tree);
+        if name == current_name {
+            return Ok(());
+        }
+
+        let mounted = match self.mounted_trees.iter_mut().find(|mounted| mounted.name == name) {
+            Some(mounted) => mounted,
+            None => return Err(Error::DaVinci(format!("No tree is open under the name '{}'", name))),
+        };
+        mem::swap(tree, &mut mounted.tree);
+        mem::swap(&mut self.selected_id_stack, &mut mounted.selected_id_stack);
+        self.tree_name = Some(mem::replace(&mut mounted.name, current_name));
+        // The last selection was in the other tree
+        self.last_selection = None;
+        Ok(())
+    }
+
+    pub fn print_help(&mut self) {
         // TODO print brackets around the first ocurrance of shortcut character
         for (command_name, command) in &self.commands {
             // TODO this should handle nice wrapping
-            println!("{}: {}", command_name, command.description);
+            self.output.println(&format!("{}: {}", command_name, command.description));
         }
     }
 
-    pub fn print_command_help(&self, command: String) {
+    pub fn print_command_help(&mut self, command: String) {
         // TODO print info on the behavior and different overloads of the given command
     }
 }
+
+// TODO this is a janky helper function that doesn't account for terminal width
+fn print_hr(output: &mut OutputSink) {
+    output.println("--------------");
+}
+
+// The name of a tree which wasn't opened under a name: its file name without
+// the extension, or main for a tree in memory
+pub fn default_tree_name(tree: &IdeaTree) -> String {
+    match tree.path().and_then(|path| path.file_stem()) {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => "main".to_string(),
+    }
+}
+
+// Split a line of input on every separator which isn't escaped with a
+// backslash. Escaped separators are unescaped, but other escapes are left
+// alone so the parts can be split again on a different separator.
+fn split_unescaped(line: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&separator) {
+            current.push(separator);
+            chars.next();
+        } else if c == separator {
+            parts.push(current);
+            current = String::new();
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+
+    parts
+}
+
+// Arguments substituted into a macro can't chain or pipe commands on their
+// own, so separators in them are escaped
+fn escape_separators(arg: &str) -> String {
+    arg.replace(";", "\\;").replace("|", "\\|")
+}
+
+fn substitute_macro_args(name: &str, line: &str, all_args: &str, args: &Vec<&str>) -> Result<String> {
+    let mut expanded = String::new();
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        match chars.peek().cloned() {
+            Some('$') => {
+                chars.next();
+                expanded.push('$');
+            },
+            Some('*') => {
+                chars.next();
+                expanded += &escape_separators(all_args);
+            },
+            Some(digit) if digit.is_digit(10) => {
+                let mut number = String::new();
+                while let Some(digit) = chars.peek().cloned() {
+                    if !digit.is_digit(10) {
+                        break;
+                    }
+                    number.push(digit);
+                    chars.next();
+                }
+
+                let arg_number = number.parse::<usize>()?;
+                if arg_number == 0 || arg_number > args.len() {
+                    return Err(Error::DaVinci(format!("Error! Macro '{}' needs at least {} argument(s)", name, arg_number)));
+                }
+                expanded += &escape_separators(args[arg_number-1]);
+            },
+            _ => expanded.push('$'),
+        }
+    }
+
+    Ok(expanded)
+}
@@ -0,0 +1,95 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::backup::*;
+use davincibot::input::*;
+use davincibot::output::*;
+use davincibot::repl::*;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+fn temp_tree(name: &str) -> (IdeaTree, PathBuf) {
+    let dir = env::temp_dir().join(format!("davincibot-test-{}-backup-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("project.dv");
+    (IdeaTree::open(&path).unwrap(), dir)
+}
+
+#[test]
+fn backups_are_rotated() {
+    let (mut tree, dir) = temp_tree("rotate");
+    let settings = tree.find_child(1, ".settings").unwrap().unwrap();
+    tree.set_description(settings, &"backup_count: 2".to_string()).unwrap();
+
+    let first = back_up(&tree).unwrap().unwrap();
+    tree.create_idea(1, "Books".to_string(), None).unwrap();
+    let second = back_up(&tree).unwrap().unwrap();
+    tree.create_idea(1, "Music".to_string(), None).unwrap();
+    let third = back_up(&tree).unwrap().unwrap();
+    assert!(first.path.starts_with(dir.join("project.backups")));
+
+    // The tree hasn't changed since the newest backup
+    assert_eq!(back_up(&tree).unwrap(), Some(third.clone()));
+
+    // Only the newest backups are kept, newest first
+    assert_eq!(list_backups(&tree).unwrap(), vec![third, second.clone()]);
+    assert!(!first.path.exists());
+
+    let restored_path = dir.join("restored.dv");
+    restore_backup(&second, &restored_path).unwrap();
+    let restored = IdeaTree::open(&restored_path).unwrap();
+    assert!(restored.find_child(1, "Books").unwrap().is_some());
+
+    // Restoring never overwrites a file
+    assert!(restore_backup(&second, &restored_path).is_err());
+
+    tree.set_description(settings, &"backup_count: 0".to_string()).unwrap();
+    assert_eq!(back_up(&tree).unwrap(), None);
+}
+
+#[test]
+fn backups_from_before_the_session_are_kept() {
+    let (mut tree, dir) = temp_tree("session");
+    let settings = tree.find_child(1, ".settings").unwrap().unwrap();
+    tree.set_description(settings, &"backup_count: 1".to_string()).unwrap();
+
+    let old_backup = dir.join("project.backups").join("project-20000101T000000Z.dv");
+    fs::create_dir_all(dir.join("project.backups")).unwrap();
+    tree.back_up_to(&old_backup).unwrap();
+
+    tree.create_idea(1, "Books".to_string(), None).unwrap();
+    let first = back_up(&tree).unwrap().unwrap();
+    tree.create_idea(1, "Music".to_string(), None).unwrap();
+    let second = back_up(&tree).unwrap().unwrap();
+
+    let backups = list_backups(&tree).unwrap();
+    assert_eq!(backups.len(), 2);
+    assert_eq!(backups[0], second);
+    assert_eq!(backups[1].path, old_backup);
+    assert!(!first.path.exists());
+}
+
+#[test]
+fn restore_backup_command() {
+    let (mut tree, dir) = temp_tree("command");
+    tree.create_idea(1, "Books".to_string(), None).unwrap();
+
+    let transcript = BufferSink::new();
+    let mut repl = Repl::with_output(Box::new(QueueInput::new(vec![])), Box::new(transcript.clone()));
+    repl.run_command(&mut tree, "backup".to_string());
+    assert!(transcript.contents().starts_with("1. "));
+
+    transcript.clear();
+    repl.run_command(&mut tree, "restore-backup 1".to_string());
+    let restored_path = dir.join("project-restored.dv");
+    assert!(transcript.contents().starts_with("Restored the backup from "));
+    assert!(IdeaTree::open(&restored_path).unwrap().find_child(1, "Books").unwrap().is_some());
+
+    transcript.clear();
+    repl.run_command(&mut tree, "restore-backup 2".to_string());
+    assert_eq!(transcript.contents(), "There is no backup number 2. Type restore-backup to list them.\n");
+}
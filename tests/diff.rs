@@ -0,0 +1,91 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::diff::*;
+
+fn changed_trees() -> (IdeaTree, IdeaTree, i64) {
+    let mut old = IdeaTree::open_in_memory().unwrap();
+    let books = old.create_idea(1, "Books".to_string(), None).unwrap();
+    old.add_tags(books, vec!["todo".to_string()]).unwrap();
+    let emma = old.create_idea(1, "Emma".to_string(), None).unwrap();
+    let music = old.create_idea(1, "Music".to_string(), None).unwrap();
+
+    // The new tree starts as a copy of the old one, with the same ids
+    let mut new = IdeaTree::open_in_memory().unwrap();
+    for name in &["Books", "Emma", "Music"] {
+        new.create_idea(1, name.to_string(), None).unwrap();
+    }
+    new.add_tags(books, vec!["todo".to_string()]).unwrap();
+
+    new.set_name(books, &"Library".to_string()).unwrap();
+    new.set_tags(books, vec!["reading".to_string()]).unwrap();
+    new.set_description(books, &"Things to read".to_string()).unwrap();
+    new.set_parent(emma, books).unwrap();
+    new.set_parent(music, books).unwrap();
+    new.set_parent(music, emma).unwrap();
+    new.create_idea(emma, "Chapter 1".to_string(), None).unwrap();
+
+    (old, new, books)
+}
+
+#[test]
+fn diff_reports_every_kind_of_change() {
+    let (old, new, _) = changed_trees();
+
+    let changes = diff_trees(&old, &new, None).unwrap();
+    assert_eq!(format_changes(&changes), "\
+renamed Library (#5): Books -> Library
+retagged Library (#5): +reading -todo
+redescribed Library (#5)
+moved Library/Emma (#6): Emma -> Library/Emma
+moved Library/Emma/Music (#7): Music -> Library/Emma/Music
++ Library/Emma/Chapter 1 (#8)
+");
+
+    let json = changes_to_json(&changes);
+    assert!(json.contains("\"change\": \"moved\""));
+    assert!(json.contains("\"path\": \"Library/Emma/Chapter 1\""));
+}
+
+#[test]
+fn diff_limited_to_subtree() {
+    let (old, mut new, books) = changed_trees();
+    let emma = new.find_child(books, "Emma").unwrap().unwrap();
+    let music = new.find_child(emma, "Music").unwrap().unwrap();
+    new.set_description(music, &"Not under Emma in the old tree".to_string()).unwrap();
+
+    // Changes to Books are left out. Music moved under Emma, so it's compared
+    // even though it wasn't there before.
+    let changes = diff_trees(&old, &new, Some(emma)).unwrap();
+    let kinds: Vec<(i64, ChangeKind)> = changes.into_iter().map(|change| (change.id, change.kind)).collect();
+    assert_eq!(kinds, vec![
+        (emma, ChangeKind::Moved),
+        (music, ChangeKind::Moved),
+        (music, ChangeKind::Redescribed),
+        (emma + 2, ChangeKind::Added),
+    ]);
+}
+
+#[test]
+fn reused_ids_are_removals_and_additions() {
+    let mut old = IdeaTree::open_in_memory().unwrap();
+    let books = old.create_idea(1, "Books".to_string(), None).unwrap();
+    let dune = old.create_idea(1, "Dune".to_string(), None).unwrap();
+
+    let mut new = IdeaTree::in_memory_from_ideas(&old.all_ideas().unwrap()).unwrap();
+    new.delete_subtree(dune).unwrap();
+    let music = new.create_idea(books, "Music".to_string(), None).unwrap();
+    assert_eq!(music, dune);
+    new.set_tags(books, vec!["to read".to_string()]).unwrap();
+
+    let changes = diff_trees(&old, &new, None).unwrap();
+    assert_eq!(format_changes(&changes), format!("\
+retagged Books (#{}): +to read
+- Dune (#{})
++ Books/Music (#{})
+", books, dune, music));
+
+    // Tags are arrays in JSON, so tags with spaces stay whole
+    let json = changes_to_json(&changes);
+    assert!(json.contains("\"new\": [\n      \"to read\"\n    ]"));
+    assert!(json.contains("\"old\": []"));
+}
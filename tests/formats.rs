@@ -0,0 +1,529 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::formats::directory::DirImport;
+use davincibot::formats::todotxt;
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process;
+
+fn temp_file(name: &str) -> String {
+    env::temp_dir().join(format!("davincibot-test-{}-{}", process::id(), name)).to_string_lossy().to_string()
+}
+
+// A subtree with the kinds of names, tags and descriptions that are easy to
+// lose in translation
+fn test_tree() -> (IdeaTree, i64) {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+
+    let books = tree.create_idea(1, "Books".to_string(), None).unwrap();
+    tree.add_tags(books, vec!["reading".to_string(), "todo".to_string()]).unwrap();
+    tree.set_description(books, &"Things to read.\n\n# Not a heading\n* Not a headline either\n".to_string()).unwrap();
+
+    let dune = tree.create_idea(books, "#1 Dune".to_string(), None).unwrap();
+    tree.add_tags(dune, vec!["scifi".to_string(), "done".to_string()]).unwrap();
+    tree.set_description(dune, &"tags: [not, real, tags]".to_string()).unwrap();
+
+    let emma = tree.create_idea(books, "Emma #classic".to_string(), None).unwrap();
+    tree.create_idea(emma, "Chapter 1".to_string(), None).unwrap();
+    tree.create_idea(books, "Neuromancer".to_string(), None).unwrap();
+
+    (tree, books)
+}
+
+fn assert_round_trip(extension: &str) {
+    let (mut tree, books) = test_tree();
+    let filename = temp_file(&format!("round-trip.{}", extension));
+
+    tree.export_idea(books, &filename).unwrap();
+    let imported = tree.create_idea(1, "Imported".to_string(), None).unwrap();
+    let new_ids = tree.import_idea(imported, &filename).unwrap();
+
+    assert_eq!(new_ids.len(), 1);
+    assert_eq!(tree.get_outline(new_ids[0]).unwrap(), tree.get_outline(books).unwrap());
+}
+
+#[test]
+fn markdown_round_trip() {
+    assert_round_trip("md");
+}
+
+#[test]
+fn markdown_round_trip_is_verbatim() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let spaced = tree.create_idea(1, "  Spaced  out ".to_string(), None).unwrap();
+    tree.add_tags(spaced, vec!["tag".to_string()]).unwrap();
+    tree.set_description(spaced, &"# One\n\\# Escaped already\n\\\\## Twice\n#\n\\".to_string()).unwrap();
+    tree.create_idea(spaced, "#".to_string(), None).unwrap();
+
+    let filename = temp_file("verbatim.md");
+    tree.export_idea(spaced, &filename).unwrap();
+    let new_ids = tree.import_idea(1, &filename).unwrap();
+    assert_eq!(tree.get_outline(new_ids[0]).unwrap(), tree.get_outline(spaced).unwrap());
+}
+
+#[test]
+fn markdown_code_blocks_are_verbatim() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let script = tree.create_idea(1, "Script".to_string(), None).unwrap();
+    tree.set_description(script, &"# Run it\n```sh\n# a comment\n~~~\n```\n".to_string()).unwrap();
+    let broken = tree.create_idea(script, "Broken".to_string(), None).unwrap();
+    tree.set_description(broken, &"~~~\n# never closed".to_string()).unwrap();
+    tree.create_idea(script, "After".to_string(), None).unwrap();
+
+    let filename = temp_file("code.md");
+    tree.export_idea(script, &filename).unwrap();
+    let mut markdown = String::new();
+    File::open(&filename).unwrap().read_to_string(&mut markdown).unwrap();
+    assert!(markdown.contains("\\# Run it\n```sh\n# a comment\n~~~\n```\n"));
+    assert!(markdown.contains("\\~~~\n\\# never closed\n"));
+
+    let new_ids = tree.import_idea(1, &filename).unwrap();
+    assert_eq!(tree.get_outline(new_ids[0]).unwrap(), tree.get_outline(script).unwrap());
+}
+
+#[test]
+fn org_round_trip() {
+    assert_round_trip("org");
+}
+
+#[test]
+fn json_round_trip() {
+    assert_round_trip("json");
+}
+
+#[test]
+fn json_merge_keeps_ids() {
+    let (mut tree, books) = test_tree();
+    let filename = temp_file("merge.json");
+    tree.export_idea(books, &filename).unwrap();
+
+    let mut copy = IdeaTree::open_in_memory().unwrap();
+    let (new_ids, errors) = copy.merge_json(1, &filename).unwrap();
+    assert_eq!(new_ids, vec![books]);
+    assert!(errors.is_empty());
+    assert_eq!(copy.get_outline(books).unwrap(), tree.get_outline(books).unwrap());
+
+    // Merging again updates the same Ideas instead of duplicating them
+    tree.set_name(books, &"Library".to_string()).unwrap();
+    tree.export_idea(books, &filename).unwrap();
+    copy.merge_json(1, &filename).unwrap();
+    assert_eq!(copy.get_outline(books).unwrap(), tree.get_outline(books).unwrap());
+    assert_eq!(copy.get_child_ids(1, true).unwrap().len(), 4);
+}
+
+#[test]
+fn json_merge_leaves_unrelated_ideas_alone() {
+    let (tree, books) = test_tree();
+    let filename = temp_file("merge-unrelated.json");
+    tree.export_idea(books, &filename).unwrap();
+
+    // The other tree uses Books' id for an unrelated Idea somewhere else
+    let mut other = IdeaTree::open_in_memory().unwrap();
+    let music = other.create_idea(1, "Music".to_string(), None).unwrap();
+    let archive = other.create_idea(1, "Archive".to_string(), None).unwrap();
+    assert_eq!(music, books);
+
+    let (new_ids, _) = other.merge_json(archive, &filename).unwrap();
+    assert!(new_ids[0] != books);
+    assert_eq!(other.get_name(music).unwrap(), "Music");
+    assert_eq!(other.get_parent_id(music).unwrap(), Some(1));
+    assert_eq!(other.get_outline(new_ids[0]).unwrap(), tree.get_outline(books).unwrap());
+
+    // An Idea which would be merged into its own subtree stays where it is,
+    // and is reported, while the rest of the merge goes ahead
+    let mut copy = IdeaTree::open_in_memory().unwrap();
+    copy.merge_json(1, &filename).unwrap();
+    let emma = copy.find_child(books, "Emma #classic").unwrap().unwrap();
+    let dune = copy.find_child(books, "#1 Dune").unwrap().unwrap();
+    copy.set_description(dune, &"Changed".to_string()).unwrap();
+    let (new_ids, errors) = copy.merge_json(emma, &filename).unwrap();
+    assert_eq!(new_ids, vec![books]);
+    let stuck_ids: Vec<i64> = errors.into_iter().map(|(id, _)| id).collect();
+    assert_eq!(stuck_ids, vec![books]);
+    assert_eq!(copy.get_parent_id(books).unwrap(), Some(1));
+    assert_eq!(copy.get_outline(books).unwrap(), tree.get_outline(books).unwrap());
+}
+
+#[test]
+fn opml_round_trip() {
+    assert_round_trip("opml");
+}
+
+#[test]
+fn html_export() {
+    let (tree, books) = test_tree();
+    let filename = temp_file("export.html");
+    tree.export_idea(books, &filename).unwrap();
+
+    let mut html = String::new();
+    File::open(&filename).unwrap().read_to_string(&mut html).unwrap();
+
+    // One of Books' three children is done
+    assert!(html.contains("<progress value=\"0.333\" max=\"1\"></progress> 33%"));
+    assert!(html.contains("<span class=\"tag\">scifi</span>"));
+    assert!(html.contains("<h1>Not a heading</h1>"));
+    assert!(!html.contains("http"));
+}
+
+#[test]
+fn html_export_is_inert() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let page = tree.create_idea(1, "Page".to_string(), None).unwrap();
+    tree.set_description(page, &"<script>alert(1)</script>\n\nSee <b>this</b> ![a tracker](http://example.com/pixel.png)\n\n[Click](javascript:alert(1)) [me](DATA:text/html,hi) [Docs](https://docs.rs) [Up](../index.html)\n".to_string()).unwrap();
+
+    let filename = temp_file("inert.html");
+    tree.export_idea(page, &filename).unwrap();
+    let mut html = String::new();
+    File::open(&filename).unwrap().read_to_string(&mut html).unwrap();
+
+    assert!(!html.contains("<script>"));
+    assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(html.contains("See &lt;b&gt;this&lt;/b&gt; a tracker"));
+    assert!(!html.contains("<img"));
+    assert!(!html.contains("example.com"));
+    assert!(!html.contains("javascript:"));
+    assert!(!html.contains("DATA:"));
+    assert!(html.contains("Click me <a href=\"https://docs.rs\">Docs</a> <a href=\"../index.html\">Up</a>"));
+}
+
+fn read_export(tree: &IdeaTree, id: i64, name: &str) -> String {
+    let filename = temp_file(name);
+    tree.export_idea(id, &filename).unwrap();
+
+    let mut contents = String::new();
+    File::open(&filename).unwrap().read_to_string(&mut contents).unwrap();
+    contents
+}
+
+#[test]
+fn dot_export() {
+    let (mut tree, books) = test_tree();
+    let dune = tree.get_child_by_name_hint(books, "Dune".to_string()).unwrap().id;
+
+    // Done Ideas are hidden by default, like in `list`
+    let dot = read_export(&tree, books, "hidden.dot");
+    assert!(dot.contains(&format!("idea{} [label=\"Books [reading] [todo]\"];", books)));
+    assert!(!dot.contains(&format!("idea{} -> idea{};", books, dune)));
+
+    let settings = tree.create_idea(books, ".settings".to_string(), None).unwrap();
+    tree.set_description(settings, &"dot_hide_ignored: false\ndot_max_depth: 1\ndot_colors:\n  done: gray".to_string()).unwrap();
+
+    let dot = read_export(&tree, books, "shown.dot");
+    assert!(dot.contains(&format!("idea{} -> idea{};", books, dune)));
+    assert!(dot.contains(&format!("idea{} [label=\"#1 Dune [scifi] [done]\", style=filled, fillcolor=\"gray\"];", dune)));
+    assert!(!dot.contains("Chapter 1"));
+}
+
+#[test]
+fn markdown_import() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let filename = temp_file("import.md");
+    File::create(&filename).unwrap().write_all(b"Preamble is ignored\n# Music #fun\nNotes\n### Guitar\n## Piano\n").unwrap();
+
+    let new_ids = tree.import_idea(1, &filename).unwrap();
+    let music = tree.get_outline(new_ids[0]).unwrap();
+
+    assert_eq!(music.name, "Music");
+    assert_eq!(music.tags, vec!["fun"]);
+    assert_eq!(music.description, "Notes");
+    let child_names: Vec<String> = music.children.into_iter().map(|child| child.name).collect();
+    assert_eq!(child_names, vec!["Guitar", "Piano"]);
+}
+
+#[test]
+fn org_import() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let filename = temp_file("import.org");
+    File::create(&filename).unwrap().write_all(b"#+TITLE: Hobbies\n* TODO Music   :fun:loud:todo:\nNotes\n*** DONE Guitar :done:\n** Piano\n** TODO list\n").unwrap();
+
+    let new_ids = tree.import_idea(1, &filename).unwrap();
+    let music = tree.get_outline(new_ids[0]).unwrap();
+
+    assert_eq!(music.name, "Music");
+    assert_eq!(music.tags, vec!["fun", "loud", "todo"]);
+    assert_eq!(music.description, "Notes");
+    assert_eq!(music.children[0].name, "Guitar");
+    assert_eq!(music.children[0].tags, vec!["done"]);
+    assert_eq!(music.children[1].name, "Piano");
+    // Without its tag, TODO is part of the name
+    assert_eq!(music.children[2].name, "TODO list");
+    assert!(music.children[2].tags.is_empty());
+}
+
+#[test]
+fn org_tags_round_trip() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let list = tree.create_idea(1, "TODO list".to_string(), None).unwrap();
+    tree.add_tags(list, vec!["todo".to_string(), "sci-fi".to_string(), "100%".to_string(), "to read".to_string()]).unwrap();
+    tree.create_idea(list, "DONE".to_string(), None).unwrap();
+    // Names ending in something like tags keep it
+    tree.create_idea(list, "Meeting :urgent:".to_string(), None).unwrap();
+    let sale = tree.create_idea(list, "Sale 50%".to_string(), None).unwrap();
+    tree.add_tags(sale, vec!["x".to_string()]).unwrap();
+
+    let filename = temp_file("tags.org");
+    tree.export_idea(list, &filename).unwrap();
+    let mut org = String::new();
+    File::open(&filename).unwrap().read_to_string(&mut org).unwrap();
+    assert_eq!(org, "* TODO TODO list :todo:sci%2Dfi:100%25:to%20read:\n** DONE\n** Meeting %3Aurgent%3A\n** Sale 50%25 :x:\n");
+
+    let new_ids = tree.import_idea(1, &filename).unwrap();
+    assert_eq!(tree.get_outline(new_ids[0]).unwrap(), tree.get_outline(list).unwrap());
+}
+
+#[test]
+fn opml_import() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let filename = temp_file("import.opml");
+    File::create(&filename).unwrap().write_all(br#"<?xml version="1.0"?>
+<opml version="2.0"><head><title>Hobbies</title></head><body>
+<outline text="Music" _note="Notes &amp; more">
+  <outline text="Guitar" _tags="loud fun"/>
+</outline>
+<outline text="Painting"/>
+</body></opml>"#).unwrap();
+
+    let new_ids = tree.import_idea(1, &filename).unwrap();
+    assert_eq!(new_ids.len(), 2);
+
+    let music = tree.get_outline(new_ids[0]).unwrap();
+    assert_eq!(music.description, "Notes & more");
+    assert_eq!(music.children[0].name, "Guitar");
+    assert_eq!(music.children[0].tags, vec!["loud", "fun"]);
+    assert_eq!(tree.get_name(new_ids[1]).unwrap(), "Painting");
+}
+
+#[test]
+fn table_round_trip() {
+    for extension in &["csv", "tsv"] {
+        let (mut tree, books) = test_tree();
+        let filename = temp_file(&format!("round-trip.{}", extension));
+        tree.export_idea(books, &filename).unwrap();
+
+        let imported = tree.create_idea(1, "Imported".to_string(), None).unwrap();
+        let (new_ids, row_errors) = tree.import_table(imported, &filename).unwrap();
+        assert_eq!(new_ids.len(), 5);
+        assert!(row_errors.is_empty());
+        assert_eq!(tree.get_outline(new_ids[0]).unwrap(), tree.get_outline(books).unwrap());
+
+        // Importing the same rows again collides with every one of them
+        let (new_ids, row_errors) = tree.import_table(imported, &filename).unwrap();
+        assert!(new_ids.is_empty());
+        let rows: Vec<usize> = row_errors.into_iter().map(|(row, _)| row).collect();
+        assert_eq!(rows, vec![2, 3, 4, 5, 6]);
+    }
+}
+
+#[test]
+fn failed_tsv_rows_leave_nothing_behind() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let filename = temp_file("failed-rows.tsv");
+    File::create(&filename).unwrap().write_all(b"path\tdescription\n\nFresh/   \tA blank name\n\nKept\t\n").unwrap();
+
+    let (new_ids, row_errors) = tree.import_table(1, &filename).unwrap();
+    assert_eq!(new_ids.len(), 1);
+    // Rows are numbered by their line in the file, blank lines included
+    let rows: Vec<usize> = row_errors.into_iter().map(|(row, _)| row).collect();
+    assert_eq!(rows, vec![3]);
+    assert_eq!(tree.find_child(1, "Fresh").unwrap(), None);
+    assert_eq!(tree.get_name(new_ids[0]).unwrap(), "Kept");
+}
+
+#[test]
+fn csv_import() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let filename = temp_file("import.csv");
+    File::create(&filename).unwrap().write_all(b"path,tags,description\nHobbies/Music/Guitar,loud fun,\"Notes, \"\"quoted\"\"\nand more\"\nHobbies/Painting,,\nHobbies/Painting,,\n,,\n\n").unwrap();
+
+    let (new_ids, row_errors) = tree.import_table(1, &filename).unwrap();
+    assert_eq!(new_ids.len(), 2);
+    assert_eq!(row_errors.len(), 1);
+    assert_eq!(row_errors[0].0, 4);
+
+    let hobbies = tree.get_outline(tree.find_child(1, "Hobbies").unwrap().unwrap()).unwrap();
+    assert_eq!(hobbies.children[0].name, "Music");
+    assert_eq!(hobbies.children[0].children[0].tags, vec!["loud", "fun"]);
+    assert_eq!(hobbies.children[0].children[0].description, "Notes, \"quoted\"\nand more");
+    assert_eq!(hobbies.children[1].name, "Painting");
+}
+
+#[test]
+fn whole_tree_table_reimports() {
+    for extension in &["csv", "tsv"] {
+        let (mut tree, _) = test_tree();
+        let filename = temp_file(&format!("whole-tree.{}", extension));
+        tree.export_idea(1, &filename).unwrap();
+
+        // The root's row, with its empty path, stands for the Idea imported into
+        let imported = tree.create_idea(1, "Imported".to_string(), None).unwrap();
+        let (_, row_errors) = tree.import_table(imported, &filename).unwrap();
+        assert!(row_errors.is_empty());
+        assert!(tree.find_child(imported, "Books").unwrap().is_some());
+    }
+}
+
+#[test]
+fn todo_txt_export() {
+    let (mut tree, books) = test_tree();
+    let neuromancer = tree.find_child(books, "Neuromancer").unwrap().unwrap();
+    tree.add_tags(neuromancer, vec!["cyberpunk".to_string(), "due:2018-11-01".to_string()]).unwrap();
+
+    let todo_txt = read_export(&tree, books, "export.todo.txt");
+    assert_eq!(todo_txt, "x #1 Dune +Books @scifi\nChapter 1 +Books +Emma_#classic\nNeuromancer +Books @cyberpunk due:2018-11-01\n");
+
+    // Other .txt files aren't todo.txt files
+    assert!(tree.export_idea(books, &temp_file("notes.txt")).is_err());
+}
+
+#[test]
+fn todo_txt_names_round_trip() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let chores = tree.create_idea(1, "Chores".to_string(), None).unwrap();
+    let names = vec!["x marks the spot", "(A) plan", "2018-10-30 notes", "Call @home", "Buy +milk", "due:friday report", "Keep \\+escaped"];
+    for name in &names {
+        tree.create_idea(chores, name.to_string(), None).unwrap();
+    }
+    let done = tree.create_idea(chores, "x done".to_string(), None).unwrap();
+    tree.add_tags(done, vec!["done".to_string()]).unwrap();
+
+    let todo_txt = read_export(&tree, chores, "names.todo.txt");
+    assert!(todo_txt.starts_with("\\x marks the spot +Chores\n\\(A) plan +Chores\n"));
+
+    let tasks = todotxt::from_todo_txt(&todo_txt);
+    let task_names: Vec<&str> = tasks.iter().map(|task| task.name.as_str()).collect();
+    let mut expected = names.clone();
+    expected.push("x done");
+    assert_eq!(task_names, expected);
+    assert!(tasks.iter().all(|task| task.projects == vec!["Chores"] && task.contexts.is_empty() && task.due.is_none()));
+    assert!(tasks[names.len()].done);
+}
+
+#[test]
+fn todo_txt_merge() {
+    let (mut tree, books) = test_tree();
+    let other = tree.create_idea(1, "Other".to_string(), None).unwrap();
+    tree.create_idea(other, "Chapter 1".to_string(), None).unwrap();
+
+    let filename = temp_file("merge.todo.txt");
+    File::create(&filename).unwrap().write_all(b"x 2018-10-30 Chapter 1 +Emma_#classic\nx Neuromancer +Books\nNot done\nx Missing\nx Chapter 1\n").unwrap();
+
+    let (done_ids, line_errors) = tree.merge_todo_txt(1, &filename).unwrap();
+    assert_eq!(done_ids.len(), 2);
+    let error_lines: Vec<usize> = line_errors.into_iter().map(|(line, _)| line).collect();
+    assert_eq!(error_lines, vec![4, 5]);
+
+    let emma = tree.find_child(books, "Emma #classic").unwrap().unwrap();
+    let chapter = tree.find_child(emma, "Chapter 1").unwrap().unwrap();
+    assert_eq!(tree.get_tags(chapter, false).unwrap(), vec!["done"]);
+}
+
+#[test]
+fn ics_export() {
+    let (mut tree, books) = test_tree();
+    let dune = tree.find_child(books, "#1 Dune").unwrap().unwrap();
+    tree.add_tags(dune, vec!["due:2018-11-01".to_string()]).unwrap();
+    let neuromancer = tree.find_child(books, "Neuromancer").unwrap().unwrap();
+    tree.set_description(neuromancer, &"scheduled: 2018-11-05\nplace: Book club; room 2".to_string()).unwrap();
+    let emma = tree.find_child(books, "Emma #classic").unwrap().unwrap();
+    tree.add_tags(emma, vec!["due:soon".to_string()]).unwrap();
+
+    // An Idea with an invalid date is skipped instead of failing the export
+    let skipped = tree.export_idea(books, &temp_file("skipped.ics")).unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert!(format!("{:?}", skipped[0]).contains(&format!("Idea #{} has a due date", emma)));
+
+    let ics = read_export(&tree, books, "export.ics");
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(ics.contains(&format!("BEGIN:VTODO\r\nUID:idea-{}.memory@davincibot\r\n", dune)));
+    assert!(ics.contains("DUE;VALUE=DATE:20181101\r\nSTATUS:COMPLETED\r\n"));
+    assert!(ics.contains("CATEGORIES:scifi,done\r\n"));
+    assert!(ics.contains(&format!("BEGIN:VEVENT\r\nUID:idea-{}.memory@davincibot\r\n", neuromancer)));
+    assert!(ics.contains("DTSTART;VALUE=DATE:20181105\r\nDESCRIPTION:scheduled: 2018-11-05\\nplace: Book club\\; room 2\r\n"));
+
+    // Undated Ideas are left out
+    assert_eq!(ics.matches("BEGIN:V").count(), 3);
+
+    // Exports of another tree have other UIDs
+    let path = std::path::PathBuf::from(temp_file("chores.dv"));
+    let _ = std::fs::remove_file(&path);
+    let mut chores = IdeaTree::open(&path).unwrap();
+    let laundry = chores.create_idea(1, "Laundry".to_string(), None).unwrap();
+    chores.add_tags(laundry, vec!["due:2018-11-01".to_string()]).unwrap();
+    let ics = read_export(&chores, 1, "chores.ics");
+    assert!(ics.contains(&format!("UID:idea-{}.davincibot-test-{}-chores@davincibot\r\n", laundry, std::process::id())));
+}
+
+#[test]
+fn directory_import_and_sync() {
+    let dir = temp_file("notes");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(format!("{}/songs/old", dir)).unwrap();
+    std::fs::create_dir_all(format!("{}/drafts", dir)).unwrap();
+    File::create(format!("{}/songs/guitar.md", dir)).unwrap().write_all(b"---\ntags: [music, fun]\ndue: 2018-11-01\n---\nPractice scales\n").unwrap();
+    File::create(format!("{}/songs/old/ballad.md", dir)).unwrap().write_all(b"Too deep").unwrap();
+    File::create(format!("{}/drafts/secret.md", dir)).unwrap().write_all(b"Hidden").unwrap();
+    File::create(format!("{}/picture.png", dir)).unwrap().write_all(b"\x89PNG").unwrap();
+    std::fs::create_dir_all(format!("{}/photos", dir)).unwrap();
+    File::create(format!("{}/photos/beach.png", dir)).unwrap().write_all(b"\x89PNG").unwrap();
+    std::fs::create_dir_all(format!("{}/lists", dir)).unwrap();
+    File::create(format!("{}/lists/todo.md", dir)).unwrap().write_all(b"Laundry").unwrap();
+
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let mut options = DirImport::new(dir.clone());
+    options.include.push("*.md".to_string());
+    options.exclude.push("drafts".to_string());
+    options.max_depth = Some(2);
+
+    let notes = tree.import_dir(1, options.clone()).unwrap();
+    let songs = tree.find_child(notes, "songs").unwrap().unwrap();
+    let guitar = tree.find_child(songs, "guitar.md").unwrap().unwrap();
+    assert_eq!(tree.get_description(guitar).unwrap(), "Practice scales\n");
+    assert_eq!(tree.get_tags(guitar, false).unwrap(), vec!["music", "fun", "due:2018-11-01"]);
+
+    let old = tree.find_child(songs, "old").unwrap().unwrap();
+    assert!(tree.get_child_ids(old, true).unwrap().is_empty());
+    assert_eq!(tree.find_child(notes, "drafts").unwrap(), None);
+    assert_eq!(tree.find_child(notes, "picture.png").unwrap(), None);
+    // Directories with nothing the globs let through are skipped
+    assert_eq!(tree.find_child(notes, "photos").unwrap(), None);
+    let lists = tree.find_child(notes, "lists").unwrap().unwrap();
+    let note = tree.create_idea(lists, "My note".to_string(), None).unwrap();
+
+    // Syncing updates the Ideas from the first import. Tags from the old
+    // front-matter are replaced, but tags added since are kept.
+    tree.add_tags(guitar, vec!["practiced".to_string()]).unwrap();
+    File::create(format!("{}/songs/guitar.md", dir)).unwrap().write_all(b"---\ntags: [music]\n---\nPractice chords\n").unwrap();
+    File::create(format!("{}/songs/piano.md", dir)).unwrap().write_all(b"").unwrap();
+    std::fs::remove_dir_all(format!("{}/songs/old", dir)).unwrap();
+    std::fs::remove_dir_all(format!("{}/lists", dir)).unwrap();
+    assert_eq!(tree.import_dir(1, options.clone()).unwrap(), notes);
+    assert_eq!(tree.sync_dir(notes).unwrap(), notes);
+
+    assert_eq!(tree.get_child_ids(1, false).unwrap(), vec![notes]);
+    assert_eq!(tree.find_child(songs, "guitar.md").unwrap(), Some(guitar));
+    assert_eq!(tree.get_description(guitar).unwrap(), "Practice chords\n");
+    assert_eq!(tree.get_tags(guitar, false).unwrap(), vec!["music", "practiced"]);
+    assert!(tree.find_child(songs, "piano.md").unwrap().is_some());
+    assert_eq!(tree.find_child(songs, "old").unwrap(), None);
+
+    // A gone directory with an Idea added under it is kept, tagged missing,
+    // until it comes back
+    assert_eq!(tree.find_child(notes, "lists").unwrap(), Some(lists));
+    assert_eq!(tree.get_tags(lists, false).unwrap(), vec!["missing"]);
+    assert_eq!(tree.get_child_ids(lists, false).unwrap(), vec![note]);
+    std::fs::create_dir_all(format!("{}/lists", dir)).unwrap();
+    File::create(format!("{}/lists/todo.md", dir)).unwrap().write_all(b"Dishes").unwrap();
+    tree.sync_dir(notes).unwrap();
+    assert!(tree.get_tags(lists, false).unwrap().is_empty());
+    let todo = tree.find_child(lists, "todo.md").unwrap().unwrap();
+    assert_eq!(tree.get_description(todo).unwrap(), "Dishes");
+
+    // An unrelated Idea with the directory's name isn't taken over
+    let mut other = IdeaTree::open_in_memory().unwrap();
+    let dir_name = std::path::Path::new(&dir).file_name().unwrap().to_string_lossy().to_string();
+    let unrelated = other.create_idea(1, dir_name, None).unwrap();
+    assert!(other.import_dir(1, options).is_err());
+    assert!(other.get_child_ids(unrelated, true).unwrap().is_empty());
+}
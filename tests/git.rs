@@ -0,0 +1,134 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::git::*;
+use davincibot::input::*;
+use davincibot::output::*;
+use davincibot::repl::*;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+fn git(dir: &Path, args: &[&str]) -> bool {
+    Command::new("git").arg("-C").arg(dir).args(args).output().unwrap().status.success()
+}
+
+// A bare repository standing in for a remote, and two clones of it
+fn test_repos(name: &str) -> (PathBuf, PathBuf) {
+    let base = env::temp_dir().join(format!("davincibot-test-{}-git-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(&base).unwrap();
+
+    assert!(git(&base, &["init", "--quiet", "--bare", "remote.git"]));
+    let ours = base.join("ours");
+    let theirs = base.join("theirs");
+    for clone in &[&ours, &theirs] {
+        assert!(git(&base, &["clone", "--quiet", "remote.git", clone.to_str().unwrap()]));
+        assert!(git(clone, &["config", "user.name", "Test"]));
+        assert!(git(clone, &["config", "user.email", "test@example.com"]));
+        assert!(git(clone, &["config", "commit.gpgsign", "false"]));
+    }
+
+    // Our clone pushes the first version of the tree, and theirs pulls it
+    {
+        let mut tree = IdeaTree::open(ours.join("project.dv")).unwrap();
+        tree.create_idea(1, "Books".to_string(), None).unwrap();
+        GitRepo::containing(tree.path().unwrap()).unwrap().commit(&tree, None).unwrap();
+    }
+    assert!(git(&ours, &["push", "--quiet", "-u", "origin", "HEAD"]));
+    assert!(git(&theirs, &["pull", "--quiet"]));
+
+    (ours, theirs)
+}
+
+#[test]
+fn commit_message_summarizes_changes() {
+    let (ours, _) = test_repos("commit");
+    let mut tree = IdeaTree::open(ours.join("project.dv")).unwrap();
+    let repo = GitRepo::containing(ours.join("project.dv")).unwrap();
+    assert_eq!(repo.commit(&tree, None).unwrap(), None);
+
+    let books = tree.find_child(1, "Books").unwrap().unwrap();
+    tree.set_name(books, &"Library".to_string()).unwrap();
+    let dune = tree.create_idea(books, "Dune".to_string(), None).unwrap();
+
+    let message = repo.commit(&tree, None).unwrap().unwrap();
+    assert!(message.starts_with("Update project.dv: 1 added, 1 changed, 0 removed\n\n"));
+    assert!(message.contains(&format!("Changed #{}: Library", books)));
+    assert!(message.contains(&format!("Added #{}: Dune", dune)));
+}
+
+#[test]
+fn status_shows_behind_and_dirty() {
+    let (ours, theirs) = test_repos("status");
+
+    {
+        let mut tree = IdeaTree::open(theirs.join("project.dv")).unwrap();
+        tree.create_idea(1, "Music".to_string(), None).unwrap();
+        GitRepo::containing(theirs.join("project.dv")).unwrap().commit(&tree, Some("Add music".to_string())).unwrap();
+    }
+    assert!(git(&theirs, &["push", "--quiet"]));
+
+    let status = check_before_open(ours.join("project.dv")).unwrap().unwrap();
+    assert_eq!(status.behind, 1);
+    assert!(!status.dirty);
+
+    {
+        let mut tree = IdeaTree::open(ours.join("project.dv")).unwrap();
+        tree.create_idea(1, "Painting".to_string(), None).unwrap();
+    }
+    assert!(check_before_open(ours.join("project.dv")).unwrap().unwrap().dirty);
+}
+
+#[test]
+fn conflicts_refuse_to_open() {
+    let (ours, theirs) = test_repos("conflicts");
+
+    for (clone, name) in &[(&ours, "Ours"), (&theirs, "Theirs")] {
+        let mut tree = IdeaTree::open(clone.join("project.dv")).unwrap();
+        tree.create_idea(1, name.to_string(), None).unwrap();
+        GitRepo::containing(clone.join("project.dv")).unwrap().commit(&tree, None).unwrap();
+    }
+    assert!(git(&theirs, &["push", "--quiet"]));
+    assert!(!git(&ours, &["pull", "--quiet", "--no-rebase"]));
+
+    assert!(check_before_open(ours.join("project.dv")).is_err());
+}
+
+#[test]
+fn diff_against_a_revision() {
+    let (ours, _) = test_repos("diff");
+    let mut tree = IdeaTree::open(ours.join("project.dv")).unwrap();
+    let repo = GitRepo::containing(ours.join("project.dv")).unwrap();
+
+    let books = tree.find_child(1, "Books").unwrap().unwrap();
+    tree.set_name(books, &"Library".to_string()).unwrap();
+    repo.commit(&tree, None).unwrap();
+    let dune = tree.create_idea(books, "Dune".to_string(), None).unwrap();
+
+    let transcript = BufferSink::new();
+    let mut repl = Repl::with_output(Box::new(QueueInput::new(vec![])), Box::new(transcript.clone()));
+    repl.run_command(&mut tree, "diff HEAD~1".to_string());
+    assert_eq!(transcript.contents(), format!("renamed Library (#{}): Books -> Library\n+ Library/Dune (#{})\n", books, dune));
+
+    transcript.clear();
+    repl.run_command(&mut tree, "diff HEAD~5".to_string());
+    assert!(transcript.contents().contains("isn't in revision HEAD~5"));
+}
+
+#[test]
+fn commits_include_the_text_mirror() {
+    let (ours, _) = test_repos("mirror");
+    let mut tree = IdeaTree::open(ours.join("project.dv")).unwrap();
+    let repo = GitRepo::containing(ours.join("project.dv")).unwrap();
+
+    tree.mirror_text(ours.join("project.dvt")).unwrap();
+    tree.create_idea(1, "Music".to_string(), None).unwrap();
+    repo.commit(&tree, None).unwrap().unwrap();
+
+    let committed = Command::new("git").arg("-C").arg(&ours).args(&["show", "--name-only", "--format=", "HEAD"]).output().unwrap();
+    let files: Vec<String> = String::from_utf8_lossy(&committed.stdout).lines().map(|line| line.to_string()).collect();
+    assert_eq!(files, vec!["project.dv", "project.dvt"]);
+    assert!(git(&ours, &["diff", "--quiet", "HEAD", "--", "project.dvt"]));
+}
@@ -0,0 +1,170 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::lock::*;
+use davincibot::input::*;
+use davincibot::output::*;
+use davincibot::repl::*;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn temp_tree_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("davincibot-test-{}-lock-{}.dv", process::id(), name));
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(lock_path(&path));
+    path
+}
+
+// Write a lock file as if another process had the tree open
+fn fake_other_session(path: &PathBuf, seconds_since_heartbeat: u64) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    fs::write(lock_path(path), format!("{}\n{}\n", process::id() + 1, now - seconds_since_heartbeat)).unwrap();
+}
+
+#[test]
+fn live_sessions_hold_the_lock() {
+    let path = temp_tree_path("live");
+    IdeaTree::open(&path).unwrap();
+
+    fake_other_session(&path, 0);
+    assert_eq!(live_session(&path).map(|holder| holder.pid), Some(process::id() + 1));
+    assert!(IdeaTree::open(&path).unwrap().lock_session().is_err());
+
+    // A session which stopped sending heartbeats has crashed
+    fake_other_session(&path, STALE_SECONDS + 1);
+    assert_eq!(live_session(&path), None);
+    {
+        let mut tree = IdeaTree::open(&path).unwrap();
+        tree.lock_session().unwrap();
+        assert!(lock_path(&path).exists());
+    }
+    assert!(!lock_path(&path).exists());
+}
+
+#[test]
+fn read_only_trees_reject_changes() {
+    let path = temp_tree_path("read-only");
+    let books = IdeaTree::open(&path).unwrap().create_idea(1, "Books".to_string(), None).unwrap();
+
+    let mut tree = IdeaTree::open_read_only(&path).unwrap();
+    assert!(tree.is_read_only());
+    assert_eq!(tree.get_name(books).unwrap(), "Books");
+    assert!(tree.set_name(books, &"Library".to_string()).is_err());
+    assert!(tree.create_idea(1, "Music".to_string(), None).is_err());
+}
+
+#[test]
+fn sessions_see_each_others_changes() {
+    let path = temp_tree_path("two-sessions");
+    let mut first = IdeaTree::open(&path).unwrap();
+    let mut second = IdeaTree::open(&path).unwrap();
+
+    let books = first.create_idea(1, "Books".to_string(), None).unwrap();
+    let music = second.create_idea(1, "Music".to_string(), None).unwrap();
+    assert_eq!(first.get_child_ids(1, false).unwrap(), vec![books, music]);
+
+    // Changing an Idea the other session deleted is an error, not a no-op
+    second.delete_subtree(books).unwrap();
+    assert!(first.set_description(books, &"Things to read".to_string()).is_err());
+}
+
+#[test]
+fn selection_follows_changes_from_other_sessions() {
+    let path = temp_tree_path("refresh");
+    let mut tree = IdeaTree::open(&path).unwrap();
+    let mut other = IdeaTree::open(&path).unwrap();
+
+    let transcript = BufferSink::new();
+    let mut repl = Repl::with_output(Box::new(QueueInput::new(vec![])), Box::new(transcript.clone()));
+    repl.run_command(&mut tree, "add Books; root; add Archive; select @/Books; add Dune".to_string());
+    let books = tree.find_child(1, "Books").unwrap().unwrap();
+    let dune = tree.find_child(books, "Dune").unwrap().unwrap();
+    let archive = tree.find_child(1, "Archive").unwrap().unwrap();
+
+    other.set_parent(books, archive).unwrap();
+    transcript.clear();
+    repl.run_command(&mut tree, "list".to_string());
+    assert!(transcript.contents().starts_with(&format!("The selected Idea #{} was moved to Archive/Books/Dune in another session.\n", dune)));
+
+    // Deleted Ideas are dropped from the selection stack
+    other.delete_subtree(books).unwrap();
+    transcript.clear();
+    repl.run_command(&mut tree, "list".to_string());
+    assert!(transcript.contents().starts_with(&format!("The selected Idea #{} was deleted in another session. Selected #{} instead.\n", dune, archive)));
+    assert_eq!(repl.selected_id(), archive);
+}
+
+#[test]
+fn read_only_repl_rejects_changes() {
+    let path = temp_tree_path("read-only-repl");
+    IdeaTree::open(&path).unwrap().create_idea(1, "Books".to_string(), None).unwrap();
+    let mut tree = IdeaTree::open_read_only(&path).unwrap();
+
+    let transcript = BufferSink::new();
+    let mut repl = Repl::with_output(Box::new(QueueInput::new(vec![])), Box::new(transcript.clone()));
+    repl.run_command(&mut tree, "add Music".to_string());
+    assert_eq!(transcript.contents(), format!("Can't use 'add' because {} is open read-only\n", default_tree_name(&tree)));
+
+    // Commands which only read the tree still work
+    transcript.clear();
+    repl.run_command(&mut tree, "list".to_string());
+    assert_eq!(transcript.contents(), "1. Books\n");
+}
+
+#[test]
+fn locked_subtrees_refuse_changes() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let books = tree.create_idea(1, "Books".to_string(), None).unwrap();
+    let dune = tree.create_idea(books, "Dune".to_string(), None).unwrap();
+    let music = tree.create_idea(1, "Music".to_string(), None).unwrap();
+    let settings = tree.create_idea(books, ".settings".to_string(), None).unwrap();
+    tree.set_description(settings, &"locked: true".to_string()).unwrap();
+
+    assert!(tree.is_locked(dune).unwrap());
+    assert!(tree.set_name(dune, &"Dune Messiah".to_string()).is_err());
+    assert!(tree.set_description(dune, &"Spice".to_string()).is_err());
+    assert!(tree.add_tags(dune, vec!["read".to_string()]).is_err());
+    assert!(tree.set_parent(dune, music).is_err());
+    assert!(tree.set_parent(books, music).is_err());
+    assert!(tree.delete_subtree(books).is_err());
+    assert_eq!(tree.get_name(dune).unwrap(), "Dune");
+
+    // Nothing can be added or moved into a locked subtree, and it can't be
+    // moved along with an ancestor
+    assert!(tree.create_idea(dune, "Chapter 1".to_string(), None).is_err());
+    assert!(tree.copy_subtree(music, books, None).is_err());
+    assert!(tree.set_parent(music, books).is_err());
+    assert_eq!(tree.get_child_ids(books, false).unwrap(), vec![dune]);
+
+    let archive = tree.create_idea(1, "Archive".to_string(), None).unwrap();
+    let sealed = tree.create_idea(archive, "Sealed".to_string(), None).unwrap();
+    let sealed_settings = tree.create_idea(sealed, ".settings".to_string(), None).unwrap();
+    tree.set_description(sealed_settings, &"locked: true".to_string()).unwrap();
+    assert!(tree.set_parent(archive, music).is_err());
+    assert_eq!(tree.get_parent_id(archive).unwrap(), Some(1));
+
+    // Other Ideas can still change, and the lock can be lifted
+    tree.set_name(music, &"Songs".to_string()).unwrap();
+    tree.set_description(settings, &"locked: false".to_string()).unwrap();
+    tree.set_name(dune, &"Dune Messiah".to_string()).unwrap();
+}
+
+#[test]
+fn replaced_files_are_reopened() {
+    let path = temp_tree_path("replaced");
+    let mut tree = IdeaTree::open(&path).unwrap();
+    tree.create_idea(1, "Books".to_string(), None).unwrap();
+    assert!(!tree.changed_externally().unwrap());
+
+    // Sync tools write a new version of the file and rename it over the old one
+    let replacement = temp_tree_path("replacement");
+    IdeaTree::open(&replacement).unwrap().create_idea(1, "Music".to_string(), None).unwrap();
+    fs::rename(&replacement, &path).unwrap();
+
+    assert!(tree.changed_externally().unwrap());
+    assert!(tree.find_child(1, "Books").unwrap().is_none());
+    assert!(tree.find_child(1, "Music").unwrap().is_some());
+}
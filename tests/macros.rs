@@ -0,0 +1,50 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::input::*;
+use davincibot::repl::*;
+
+fn test_tree(macros: &str) -> IdeaTree {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let macros_id = tree.create_idea(1, ".macros".to_string(), None).unwrap();
+    tree.set_description(macros_id, &macros.to_string()).unwrap();
+
+    tree
+}
+
+#[test]
+fn macro_arguments_are_substituted() {
+    let mut tree = test_tree("book:\n  - add Books/$*\n  - tag $$unread\nfile: move $1 -> $2");
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    repl.run_command(&mut tree, "add Books; root".to_string());
+    let results = repl.run_command(&mut tree, "book The Left Hand of Darkness".to_string());
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(tree.get_name(results[0]).unwrap(), "The Left Hand of Darkness");
+    assert_eq!(tree.get_tags(results[0], false).unwrap(), vec!["$unread"]);
+}
+
+#[test]
+fn macros_are_scoped_to_subtrees() {
+    let mut tree = test_tree("shelve: tag shelved");
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    repl.run_command(&mut tree, "add Books; add .macros".to_string());
+    let books = tree.get_child_by_name_hint(1, "Books".to_string()).unwrap();
+
+    // The empty .macros Idea under Books hides the root's macros
+    let results = repl.run_command(&mut tree, format!("select #{}; shelve", books.id));
+    assert_eq!(results, Vec::<i64>::new());
+    assert_eq!(tree.get_tags(books.id, false).unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn recursive_macros_are_stopped() {
+    let mut tree = test_tree("ping: pong\npong: tag ponged; ping");
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    let results = repl.run_command(&mut tree, "ping".to_string());
+
+    assert_eq!(results, Vec::<i64>::new());
+    assert_eq!(tree.get_tags(1, false).unwrap(), vec!["ponged"]);
+}
@@ -0,0 +1,137 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::merge::*;
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn tree_from_ideas(ideas: &[Idea], name: &str) -> IdeaTree {
+    let path = env::temp_dir().join(format!("davincibot-test-{}-merge-{}.dv", process::id(), name));
+    let _ = fs::remove_file(&path);
+    IdeaTree::create_from_ideas(&path, ideas).unwrap()
+}
+
+// Copy a tree into a new file, the way copies of a .dv file get passed around
+fn copy_tree(tree: &IdeaTree, name: &str) -> IdeaTree {
+    tree_from_ideas(&tree.all_ideas().unwrap(), name)
+}
+
+fn base_tree() -> (IdeaTree, i64, i64) {
+    let mut base = IdeaTree::open_in_memory().unwrap();
+    let books = base.create_idea(1, "Books".to_string(), None).unwrap();
+    base.add_tags(books, vec!["reading".to_string(), "old".to_string()]).unwrap();
+    base.set_description(books, &"Things to read".to_string()).unwrap();
+    let dune = base.create_idea(1, "Dune".to_string(), None).unwrap();
+
+    (base, books, dune)
+}
+
+#[test]
+fn independent_changes_merge_automatically() {
+    let (base, books, dune) = base_tree();
+    let mut ours = copy_tree(&base, "auto-ours");
+    let mut theirs = copy_tree(&base, "auto-theirs");
+
+    ours.set_name(books, &"Library".to_string()).unwrap();
+    ours.add_tags(books, vec!["ours".to_string()]).unwrap();
+    let our_new = ours.create_idea(1, "Music".to_string(), None).unwrap();
+
+    theirs.set_description(books, &"Things to read soon".to_string()).unwrap();
+    theirs.remove_tags(books, vec!["old".to_string()]).unwrap();
+    theirs.add_tags(books, vec!["theirs".to_string()]).unwrap();
+    theirs.set_parent(dune, books).unwrap();
+    let their_new = theirs.create_idea(1, "Painting".to_string(), None).unwrap();
+    assert_eq!(our_new, their_new);
+
+    let result = merge_trees(&base, &ours, &theirs).unwrap();
+    assert_eq!(result.conflicts, vec![]);
+
+    let merged = tree_from_ideas(&result.ideas, "auto-merged");
+
+    let books_idea = merged.get_idea(books).unwrap();
+    assert_eq!(books_idea.name, "Library");
+    assert_eq!(books_idea.description, "Things to read soon");
+    assert_eq!(books_idea.tags, vec!["reading", "ours", "theirs"]);
+    assert_eq!(books_idea.child_ids, vec![dune]);
+    assert_eq!(merged.get_parent_id(dune).unwrap(), Some(books));
+
+    // Both new Ideas are kept, under different ids
+    assert!(merged.find_child(1, "Music").unwrap().is_some());
+    assert!(merged.find_child(1, "Painting").unwrap().is_some());
+}
+
+#[test]
+fn reused_ids_are_not_merged() {
+    let (base, books, dune) = base_tree();
+    let mut ours = copy_tree(&base, "reused-ours");
+    let mut theirs = copy_tree(&base, "reused-theirs");
+
+    // Deleting the newest Idea frees its id for the next one
+    ours.delete_subtree(dune).unwrap();
+    let music = ours.create_idea(books, "Music".to_string(), None).unwrap();
+    assert_eq!(music, dune);
+    theirs.set_description(dune, &"Spice".to_string()).unwrap();
+
+    let result = merge_trees(&base, &ours, &theirs).unwrap();
+    let merged = tree_from_ideas(&result.ideas, "reused-merged");
+
+    let music = merged.find_child(books, "Music").unwrap().unwrap();
+    assert!(music != dune);
+    assert_eq!(merged.get_description(music).unwrap(), "");
+}
+
+#[test]
+fn renamed_and_moved_ideas_are_merged() {
+    let (base, books, dune) = base_tree();
+    let mut ours = copy_tree(&base, "renamed-ours");
+    let mut theirs = copy_tree(&base, "renamed-theirs");
+
+    // Dune still exists with a larger id, so Books' id can't have been reused
+    ours.set_name(books, &"Library".to_string()).unwrap();
+    ours.set_parent(books, dune).unwrap();
+    theirs.set_description(books, &"Things to read soon".to_string()).unwrap();
+
+    let result = merge_trees(&base, &ours, &theirs).unwrap();
+    assert_eq!(result.conflicts, vec![]);
+
+    let merged = tree_from_ideas(&result.ideas, "renamed-merged");
+    assert_eq!(merged.get_name(books).unwrap(), "Library");
+    assert_eq!(merged.get_parent_id(books).unwrap(), Some(dune));
+    assert_eq!(merged.get_description(books).unwrap(), "Things to read soon");
+    assert_eq!(merged.get_child_ids(dune, true).unwrap(), vec![books]);
+    assert!(merged.find_child(1, "Books").unwrap().is_none());
+}
+
+#[test]
+fn conflicting_changes_are_reported() {
+    let (base, books, dune) = base_tree();
+    let mut ours = copy_tree(&base, "conflict-ours");
+    let mut theirs = copy_tree(&base, "conflict-theirs");
+
+    ours.set_description(books, &"Ours".to_string()).unwrap();
+    theirs.set_description(books, &"Theirs".to_string()).unwrap();
+    ours.set_parent(dune, books).unwrap();
+    theirs.set_parent(books, dune).unwrap();
+
+    let result = merge_trees(&base, &ours, &theirs).unwrap();
+    assert_eq!(result.conflicts.len(), 2);
+    assert_eq!(result.conflicts[0].field, "description");
+    assert_eq!(result.conflicts[0].ours, Some("Ours".to_string()));
+    assert_eq!(result.conflicts[0].theirs, Some("Theirs".to_string()));
+    assert_eq!(result.conflicts[1].field, "parent");
+
+    let mut merged = tree_from_ideas(&result.ideas, "conflict-merged");
+
+    // Our side wins until the conflicts are resolved by hand
+    assert_eq!(merged.get_description(books).unwrap(), "Ours");
+    assert_eq!(merged.get_parent_id(dune).unwrap(), Some(books));
+    assert_eq!(merged.get_parent_id(books).unwrap(), Some(1));
+
+    let conflicts = add_conflict_ideas(&mut merged, &result.conflicts).unwrap();
+    let conflict = merged.find_child(conflicts, &format!("description of #{} Books", books)).unwrap().unwrap();
+    assert!(merged.get_description(conflict).unwrap().ends_with("<<<<<<< ours\nOurs\n=======\nTheirs\n>>>>>>> theirs"));
+
+    // Conflicts from a later merge go under the same Idea
+    assert_eq!(add_conflict_ideas(&mut merged, &result.conflicts).unwrap(), conflicts);
+}
@@ -0,0 +1,124 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::formats::text;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("davincibot-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn tree_text(tree: &IdeaTree) -> String {
+    text::to_text(&tree.all_ideas().unwrap())
+}
+
+fn make_changes(tree: &mut IdeaTree) {
+    let books = tree.create_idea(1, "Books".to_string(), None).unwrap();
+    tree.add_tags(books, vec!["reading".to_string()]).unwrap();
+    tree.set_description(books, &"Things to read\n\n| Not a bar line\n".to_string()).unwrap();
+    let dune = tree.create_idea(1, "Dune".to_string(), None).unwrap();
+    tree.set_parent(dune, books).unwrap();
+    tree.set_name(dune, &"Dune\\Messiah".to_string()).unwrap();
+}
+
+#[test]
+fn text_file_mirror() {
+    let mirror = temp_path("mirror.dvt");
+    let mut tree = IdeaTree::open(temp_path("mirror.dv")).unwrap();
+    tree.mirror_text(&mirror).unwrap();
+    make_changes(&mut tree);
+
+    let text = fs::read_to_string(&mirror).unwrap();
+    assert!(text.starts_with("davincibot text 1\n\n[1]\n"));
+    assert!(text.contains("name: Dune\\\\Messiah\n"));
+    assert_eq!(text, tree_text(&tree));
+
+    // The mirror loads back into the same tree, and keeps being written
+    let mut loaded = IdeaTree::open(&mirror).unwrap();
+    assert_eq!(tree_text(&loaded), tree_text(&tree));
+    loaded.use_text_mirror().unwrap();
+    loaded.create_idea(1, "Music".to_string(), None).unwrap();
+    assert_eq!(tree_text(&IdeaTree::open(&mirror).unwrap()), tree_text(&loaded));
+}
+
+#[test]
+fn text_directory_mirror_from_setting() {
+    let dv_file = temp_path("dir-mirror.dv");
+    let mirror = temp_path("dir-mirror");
+    {
+        let mut tree = IdeaTree::open(&dv_file).unwrap();
+        let settings = tree.find_child(1, ".settings").unwrap().unwrap();
+        let setting = format!("text_mirror: {}", mirror.file_name().unwrap().to_string_lossy());
+        tree.set_description(settings, &setting).unwrap();
+    }
+
+    // Only the session editing the tree writes the mirror. Opening it to
+    // read it, like diff and merge do, leaves the mirror alone.
+    let mut tree = IdeaTree::open(&dv_file).unwrap();
+    tree.create_idea(1, "Scratch".to_string(), None).unwrap();
+    assert!(!mirror.exists());
+
+    let mut tree = IdeaTree::open(&dv_file).unwrap();
+    tree.use_text_mirror().unwrap();
+    make_changes(&mut tree);
+
+    let books = tree.find_child(1, "Books").unwrap().unwrap();
+    let books_text = fs::read_to_string(mirror.join(format!("{}.idea", books))).unwrap();
+    assert!(books_text.contains("tag: reading\n| Things to read\n|\n| | Not a bar line\n|\n"));
+
+    assert_eq!(tree_text(&IdeaTree::open(&mirror).unwrap()), tree_text(&tree));
+}
+
+#[test]
+fn text_mirror_changed_outside_the_session() {
+    let mirror = temp_path("outside.dvt");
+    let mut other = IdeaTree::open(temp_path("outside.dv")).unwrap();
+    other.mirror_text(&mirror).unwrap();
+
+    let mut tree = IdeaTree::open(&mirror).unwrap();
+    tree.use_text_mirror().unwrap();
+    other.create_idea(1, "Books".to_string(), None).unwrap();
+
+    // Writing the mirror would lose the other change, so it's loaded instead
+    assert!(tree.create_idea(1, "Music".to_string(), None).is_err());
+    assert!(tree.changed_externally().unwrap());
+    assert!(tree.find_child(1, "Books").unwrap().is_some());
+
+    tree.create_idea(1, "Music".to_string(), None).unwrap();
+    let loaded = IdeaTree::open(&mirror).unwrap();
+    assert!(loaded.find_child(1, "Books").unwrap().is_some());
+    assert!(loaded.find_child(1, "Music").unwrap().is_some());
+}
+
+fn mirror_files(mirror: &PathBuf) -> Vec<(PathBuf, String)> {
+    let mut files: Vec<(PathBuf, String)> = fs::read_dir(mirror).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .map(|file| (file.clone(), fs::read_to_string(&file).unwrap()))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn failed_changes_leave_the_mirror_alone() {
+    let mirror = temp_path("failed-mirror");
+    let mut tree = IdeaTree::open(temp_path("failed-mirror.dv")).unwrap();
+    tree.mirror_text(&mirror).unwrap();
+    make_changes(&mut tree);
+    let before = mirror_files(&mirror);
+
+    // The row creates its parent before its blank name is refused
+    let table = temp_path("failed-mirror.tsv");
+    fs::write(&table, "path\nFresh/   \n").unwrap();
+    let (new_ids, row_errors) = tree.import_table(1, &table.to_string_lossy().to_string()).unwrap();
+    assert!(new_ids.is_empty());
+    assert_eq!(row_errors.len(), 1);
+
+    assert_eq!(mirror_files(&mirror), before);
+}
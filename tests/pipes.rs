@@ -0,0 +1,57 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::input::*;
+use davincibot::repl::*;
+
+#[test]
+fn chained_commands_run_in_order() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    repl.run_command(&mut tree, "add Books; add Dune; tag reading".to_string());
+
+    let books = tree.get_child_by_name_hint(1, "Books".to_string()).unwrap();
+    let dune = tree.get_child_by_name_hint(books.id, "Dune".to_string()).unwrap();
+    assert_eq!(dune.tags, vec!["reading"]);
+}
+
+#[test]
+fn chain_stops_at_error() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    let results = repl.run_command(&mut tree, "select nonexistent; add Dune".to_string());
+
+    assert_eq!(results, Vec::<i64>::new());
+    assert!(tree.get_child_by_name_hint(1, "Dune".to_string()).is_err());
+}
+
+#[test]
+fn pipe_runs_command_on_each_result() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    repl.run_command(&mut tree, "add guitar tabs; root; add guitar strings; root".to_string());
+
+    let tagged_ids = repl.run_command(&mut tree, "search guitar | tag music".to_string());
+    assert_eq!(tagged_ids.len(), 2);
+    for id in tagged_ids {
+        assert_eq!(tree.get_tags(id, false).unwrap(), vec!["music"]);
+    }
+
+    // Piped commands don't change the selection
+    assert_eq!(repl.selected_id(), 1);
+}
+
+#[test]
+fn listall_pipes_skip_meta_ideas() {
+    let mut tree = IdeaTree::open_in_memory().unwrap();
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
+
+    repl.run_command(&mut tree, "add Books; root; add Music; tag done; root".to_string());
+
+    let tagged_ids = repl.run_command(&mut tree, "listall | tag x".to_string());
+    assert_eq!(tagged_ids.len(), 2);
+    let ignore = tree.find_child(1, ".ignore").unwrap().unwrap();
+    assert!(!tree.get_tags(ignore, false).unwrap().contains(&"x".to_string()));
+}
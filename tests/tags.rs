@@ -1,10 +1,11 @@
 extern crate davincibot;
 use davincibot::idea::*;
+use davincibot::input::*;
 use davincibot::repl::*;
 
 fn test_tree() -> IdeaTree {
     let mut tree = IdeaTree::open_in_memory().unwrap();
-    let mut repl = Repl::new();
+    let mut repl = Repl::new(Box::new(QueueInput::new(vec![])));
 
     // The root Idea (id 1) will have tags "test1" and "test2"
     repl.run_command(&mut tree, "cleartags".to_string());
@@ -0,0 +1,160 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::input::*;
+use davincibot::output::*;
+use davincibot::repl::*;
+
+// A scripted Repl session which captures everything the Repl prints
+struct Session {
+    tree: IdeaTree,
+    repl: Repl,
+    transcript: BufferSink,
+}
+
+impl Session {
+    fn new() -> Session {
+        Session::with_input(vec![])
+    }
+
+    // Commands which prompt for more input will read these lines
+    fn with_input(lines: Vec<&str>) -> Session {
+        let transcript = BufferSink::new();
+
+        Session {
+            tree: IdeaTree::open_in_memory().unwrap(),
+            repl: Repl::with_output(Box::new(QueueInput::new(lines)), Box::new(transcript.clone())),
+            transcript,
+        }
+    }
+
+    // Run one line of input and return everything it printed
+    fn run(&mut self, line: &str) -> String {
+        self.transcript.clear();
+        self.repl.run_command(&mut self.tree, line.to_string());
+        self.transcript.contents()
+    }
+}
+
+#[test]
+fn version() {
+    let mut session = Session::new();
+    assert_eq!(session.run("version"), format!("{}\n", VERSION));
+}
+
+#[test]
+fn select_prints_summary() {
+    let mut session = Session::new();
+    let output = session.run("add Books");
+    let books = session.tree.get_child_by_name_hint(1, "Books".to_string()).unwrap();
+
+    assert_eq!(output, format!("--------------\n#{}: Books\n--------------\n", books.id));
+}
+
+#[test]
+fn list_children() {
+    let mut session = Session::new();
+    session.run("add Books; root; add Music; tag done; root");
+
+    assert_eq!(session.run("list"), "1. Books\n");
+    assert_eq!(session.run("listall"), "1. Books\nHidden: .ignore\nHidden: .settings\nHidden: .shortcuts\nHidden: Music [done]\n");
+}
+
+#[test]
+fn unknown_command() {
+    let mut session = Session::new();
+    assert_eq!(session.run("frobnicate"), "There is no Da Vinci Bot command named frobnicate\n");
+}
+
+#[test]
+fn progress_bar_printer() {
+    let mut session = Session::new();
+    session.run("add Project; tag todo; add Step; tag done");
+
+    let output = session.run("up");
+    assert!(output.ends_with("1 children\n--------------\n[=============DONE!============]\n--------------\n"));
+}
+
+#[test]
+fn move_multiple() {
+    let mut session = Session::with_input(vec!["Books", "Dune", "Emma", "exit"]);
+    session.run("add Books; root; add Dune; root; add Emma; root");
+
+    session.run("move");
+    assert_eq!(session.run("list"), "1. Books\n");
+    session.run("select Books");
+    assert_eq!(session.run("list"), "1. Dune\n2. Emma\n");
+}
+
+#[test]
+fn tag_multiple() {
+    let mut session = Session::with_input(vec!["music fun", "1", "2", "exit"]);
+    session.run("add guitar; root; add piano; root");
+
+    session.run("tag");
+    assert_eq!(session.run("list"), "1. guitar [music] [fun]\n2. piano [music] [fun]\n");
+}
+
+#[test]
+fn prompts_end_with_input() {
+    let mut session = Session::with_input(vec!["first", "second"]);
+
+    let results = session.repl.run_command(&mut session.tree, "add".to_string());
+    assert_eq!(results.len(), 2);
+    assert_eq!(session.run("list"), "1. first\n2. second\n");
+}
+
+#[test]
+fn copy_subtree() {
+    let mut session = Session::new();
+    session.run("add Books; tag reading; add Dune; root; add Archive; root");
+
+    session.run("copy Books -> Archive");
+    session.run("copy Books -> @ -> Books 2");
+    assert_eq!(session.run("list"), "1. Books [reading]\n2. Archive\n3. Books 2 [reading]\n");
+    session.run("select Archive");
+    assert_eq!(session.run("list"), "1. Books [reading]\n");
+    session.run("select Books");
+    assert_eq!(session.run("list"), "1. Dune\n");
+}
+
+#[test]
+fn new_from_template() {
+    let mut session = Session::new();
+    session.run("add .templates; add Meeting; add Notes for {{name}}; root");
+    let meeting = session.tree.get_idea(session.tree.find_child(1, ".templates").unwrap().unwrap()).unwrap().child_ids[0];
+    session.tree.add_tags(meeting, vec!["scheduled:{{date}}".to_string()]).unwrap();
+    session.tree.set_description(meeting, &"Meeting about {{name}}".to_string()).unwrap();
+
+    assert_eq!(session.run("new"), "Meeting\n");
+    session.run("new meet Budget review");
+    let new_id = session.repl.selected_id();
+    let idea = session.tree.get_idea(new_id).unwrap();
+    assert_eq!(idea.name, "Budget review");
+    assert_eq!(idea.tags, vec![format!("scheduled:{}", today())]);
+    assert_eq!(idea.description, "Meeting about Budget review");
+    assert_eq!(session.tree.get_name(idea.child_ids[0]).unwrap(), "Notes for Budget review");
+}
+
+#[test]
+fn describe_rejects_invalid_yaml() {
+    let mut session = Session::with_input(vec!["path: [unclosed", "n"]);
+    session.run("add Notes; add .import-dir");
+    let notes = session.tree.find_child(1, "Notes").unwrap().unwrap();
+    let record = session.tree.find_child(notes, ".import-dir").unwrap().unwrap();
+
+    let output = session.run("describe");
+    assert!(output.contains("The description of .import-dir must be valid YAML"));
+    assert!(output.ends_with("Description unchanged.\n"));
+    assert_eq!(session.tree.get_description(record).unwrap(), "");
+}
+
+#[test]
+fn describe_without_changes() {
+    let mut session = Session::with_input(vec!["Things to read"]);
+    session.run("add Books");
+    let books = session.tree.find_child(1, "Books").unwrap().unwrap();
+    session.tree.set_description(books, &"Things to read".to_string()).unwrap();
+
+    assert!(session.run("describe").ends_with("Description unchanged.\n"));
+    assert_eq!(session.tree.get_description(books).unwrap(), "Things to read");
+}
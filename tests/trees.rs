@@ -0,0 +1,86 @@
+extern crate davincibot;
+use davincibot::idea::*;
+use davincibot::input::*;
+use davincibot::output::*;
+use davincibot::repl::*;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+fn temp_tree_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("davincibot-test-{}-{}.dv", process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+// A Repl session on a personal tree with a team tree opened alongside it
+fn open_session(test: &str) -> (IdeaTree, Repl, BufferSink, PathBuf) {
+    let personal_path = temp_tree_path(&format!("{}-personal", test));
+    let team_path = temp_tree_path(&format!("{}-team", test));
+
+    let mut team = IdeaTree::open(&team_path).unwrap();
+    team.create_idea(1, "Backlog".to_string(), None).unwrap();
+
+    let transcript = BufferSink::new();
+    let mut tree = IdeaTree::open(&personal_path).unwrap();
+    let mut repl = Repl::with_output(Box::new(QueueInput::new(vec![])), Box::new(transcript.clone()));
+    repl.run_command(&mut tree, format!("open {} -> team", team_path.display()));
+
+    (tree, repl, transcript, team_path)
+}
+
+#[test]
+fn switch_between_open_trees() {
+    let (mut tree, mut repl, transcript, _) = open_session("switch");
+    let personal_name = default_tree_name(&tree);
+
+    transcript.clear();
+    repl.run_command(&mut tree, "switch".to_string());
+    assert_eq!(transcript.contents(), format!("* {}\n  team\n", personal_name));
+
+    repl.run_command(&mut tree, "switch team".to_string());
+    assert_eq!(repl.tree_name(&tree), "team");
+    assert!(tree.find_child(1, "Backlog").unwrap().is_some());
+
+    // Selecting with a tree prefix switches to that tree
+    repl.run_command(&mut tree, format!("select {}:@", personal_name));
+    assert_eq!(repl.tree_name(&tree), personal_name);
+
+    // The current tree can't be closed, but other trees can
+    transcript.clear();
+    repl.run_command(&mut tree, format!("close {}", personal_name));
+    assert!(transcript.contents().contains("current tree"));
+    repl.run_command(&mut tree, "close team".to_string());
+    assert_eq!(repl.tree_names(&tree), vec![personal_name]);
+}
+
+#[test]
+fn move_across_trees() {
+    let (mut tree, mut repl, transcript, team_path) = open_session("move");
+
+    repl.run_command(&mut tree, "add Report; tag urgent; add Draft".to_string());
+    let report = tree.find_child(1, "Report").unwrap().unwrap();
+    tree.set_description(report, &"Due friday".to_string()).unwrap();
+
+    transcript.clear();
+    repl.run_command(&mut tree, "move @/Report -> team:Backlog".to_string());
+    assert_eq!(tree.find_child(1, "Report").unwrap(), None);
+    assert_eq!(transcript.contents(), "Moved to team:Backlog/Report\n");
+
+    // Nothing is copied if the Idea can't be deleted afterward
+    transcript.clear();
+    repl.run_command(&mut tree, "move @ -> team:Backlog".to_string());
+    assert!(transcript.contents().contains("Cannot move the Root idea."));
+    repl.run_command(&mut tree, "close team".to_string());
+
+    // The whole subtree is in the team tree, with its tags and description
+    let team = IdeaTree::open(&team_path).unwrap();
+    let backlog = team.find_child(1, "Backlog").unwrap().unwrap();
+    let moved = team.get_idea(team.find_child(backlog, "Report").unwrap().unwrap()).unwrap();
+    assert_eq!(moved.tags, vec!["urgent"]);
+    assert_eq!(moved.description, "Due friday");
+    assert!(team.find_child(moved.id, "Draft").unwrap().is_some());
+    assert_eq!(team.get_child_ids(backlog, true).unwrap(), vec![moved.id]);
+}